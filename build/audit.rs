@@ -0,0 +1,245 @@
+// checks that the library we're about to link actually provides the functions we
+// generated bindings for, and that it agrees with the rest of the build
+
+use ::BuildMeta;
+use ::std::fmt;
+use ::std::collections::BTreeSet;
+use ::std::process::Command;
+use ::path_abs::PathFile;
+
+pub(crate) enum AuditError {
+    // The header and library disagree.
+    Missing { lib_file: PathFile, symbols: Vec<String> },
+    // liblammps and mpi-sys would use different implementations of MPI.
+    MpiMismatch { lib_file: PathFile, lammps: MpiImpl, mpicc: MpiImpl },
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuditError::Missing { lib_file, symbols } => {
+                writeln!(f, "bindings were generated for functions that are not exported by {}:", lib_file.display())?;
+                for symbol in symbols {
+                    writeln!(f, "    {}", symbol)?;
+                }
+                write!(f, "\
+                    This usually means that the header and the library came from different \
+                    LAMMPS installations. (check the -I and -L flags reported by pkg-config)\
+                ")
+            },
+            AuditError::MpiMismatch { lib_file, lammps, mpicc } => {
+                writeln!(f, "{} is linked against {}, but mpicc uses {}.", lib_file.display(), lammps, mpicc)?;
+                write!(f, "\
+                    Mixing implementations of MPI will lead to segfaults.  Make sure that `mpicc` \
+                    (or $MPICC) belongs to the same implementation that LAMMPS was built with.\
+                ")
+            },
+        }
+    }
+}
+
+/// Compare the functions declared in the generated bindings against the
+/// symbols exported by the library.
+///
+/// If we can't read the library's symbols for whatever reason, the check is skipped.
+pub(crate) fn audit_symbols(meta: &BuildMeta, code: &str) -> Result<(), AuditError> {
+    let lib_file = match meta.lib_file {
+        Some(ref lib_file) => lib_file,
+        None => return Ok(()),
+    };
+
+    let exported = match exported_symbols(lib_file) {
+        Ok(exported) => exported,
+        Err(e) => {
+            println!("cargo:warning=skipping symbol check for {}: {}", lib_file.display(), e);
+            return Ok(());
+        },
+    };
+
+    let mut declared: Vec<String> = {
+        ::codegen::extern_fns(code).into_iter()
+            .map(|func| func.name.to_string())
+            .collect()
+    };
+    // declared by hand in src/lib.rs
+    if cfg!(feature = "mpi") {
+        declared.push("lammps_open".to_string());
+    }
+
+    let missing: Vec<_> = declared.into_iter().filter(|name| !exported.contains(name)).collect();
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(AuditError::Missing { lib_file: lib_file.clone(), symbols: missing }),
+    }
+}
+
+// Read the dynamic symbol table (or, for archives, the symbol tables of all members).
+pub(crate) fn exported_symbols(lib_file: &PathFile) -> Result<BTreeSet<String>, String> {
+    let is_archive = lib_file.extension() == Some("a".as_ref());
+    let is_apple = ::env::target().contains("-apple-");
+
+    let mut cmd = Command::new(::env::nm());
+    match (is_apple, is_archive) {
+        (true, _) => cmd.arg("-gU"),
+        (false, true) => cmd.arg("-g").arg("--defined-only"),
+        (false, false) => cmd.arg("-D").arg("--defined-only"),
+    };
+    cmd.arg(lib_file.as_path());
+
+    let stdout = run_for_stdout(&mut cmd)?;
+
+    Ok({
+        stdout.lines()
+            // lines look like "0000000000123456 T lammps_open_no_mpi".
+            // (archives also have lines like "library.cpp.o:" that we want to skip)
+            .filter_map(|line| {
+                let words: Vec<_> = line.split_whitespace().collect();
+                match words.len() {
+                    2 | 3 => Some(words[words.len() - 1]),
+                    _ => None,
+                }
+            })
+            // C symbols on macOS have a leading underscore
+            .map(|name| match is_apple && name.starts_with('_') {
+                true => name[1..].to_string(),
+                false => name.to_string(),
+            })
+            .collect()
+    })
+}
+
+// ----------------------------------------------------
+
+/// A family of ABI-compatible MPI implementations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum MpiImpl {
+    OpenMpi,
+    // MPICH and its derivatives (Intel MPI, MVAPICH, Cray MPICH), which share an ABI.
+    Mpich,
+}
+
+impl fmt::Display for MpiImpl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MpiImpl::OpenMpi => write!(f, "Open MPI"),
+            MpiImpl::Mpich => write!(f, "MPICH (or an ABI-compatible derivative)"),
+        }
+    }
+}
+
+/// Make sure that LAMMPS and `mpi-sys` will agree on the implementation of MPI.
+///
+/// This is only a heuristic; if either side can't be identified, the check is skipped.
+pub(crate) fn audit_mpi(meta: &BuildMeta) -> Result<(), AuditError> {
+    if !cfg!(feature = "mpi") {
+        return Ok(());
+    }
+    let lib_file = match meta.lib_file {
+        Some(ref lib_file) => lib_file,
+        None => return Ok(()),
+    };
+
+    let lammps = match lammps_mpi_impl(lib_file) {
+        Ok(Some(imp)) => imp,
+        Ok(None) => return Ok(()), // e.g. LAMMPS was linked to the MPI STUBS library
+        Err(e) => {
+            println!("cargo:warning=skipping MPI check for {}: {}", lib_file.display(), e);
+            return Ok(());
+        },
+    };
+    let mpicc = match mpicc_mpi_impl() {
+        Ok(imp) => imp,
+        Err(e) => {
+            println!("cargo:warning=skipping MPI check: {}", e);
+            return Ok(());
+        },
+    };
+
+    match lammps == mpicc {
+        true => Ok(()),
+        false => Err(AuditError::MpiMismatch { lib_file: lib_file.clone(), lammps, mpicc }),
+    }
+}
+
+// Identify the libmpi that a shared liblammps depends on.
+fn lammps_mpi_impl(lib_file: &PathFile) -> Result<Option<MpiImpl>, String> {
+    if ::env::is_cross() {
+        return Err(String::from("can't inspect dependencies when cross-compiling"));
+    }
+
+    let (tool, args) = match ::env::target().contains("-apple-") {
+        true => ("otool", &["-L"][..]),
+        false => ("ldd", &[][..]),
+    };
+    let mut cmd = Command::new(tool);
+    cmd.args(args).arg(lib_file.as_path());
+    let stdout = run_for_stdout(&mut cmd)?;
+
+    // ldd lines look like "libmpi.so.40 => /usr/lib/x86_64-linux-gnu/libmpi.so.40 (0x...)"
+    // otool lines look like "/usr/local/opt/open-mpi/lib/libmpi.40.dylib (compatibility ...)"
+    let mpi_line = stdout.lines().map(str::trim).find(|line| {
+        let name = line.split_whitespace().next().unwrap_or("");
+        let name = name.rsplit('/').next().unwrap();
+        name.starts_with("libmpi.") || name.starts_with("libmpich")
+    });
+    let mpi_line = match mpi_line {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+
+    if let Some(imp) = mpi_impl_from_text(mpi_line) {
+        return Ok(Some(imp));
+    }
+
+    // The path didn't give it away, so look inside the library.
+    let path = match mpi_line.find("=>") {
+        Some(i) => &mpi_line[i + 2..],
+        None => mpi_line,
+    };
+    let path = path.split_whitespace().next().unwrap_or("");
+    let mpi_file = PathFile::new(path).map_err(|e| e.to_string())?;
+    let symbols = exported_symbols(&mpi_file)?;
+    Ok(Some(match symbols.contains("ompi_mpi_comm_world") {
+        true => MpiImpl::OpenMpi,
+        false => MpiImpl::Mpich,
+    }))
+}
+
+// Identify the implementation behind `mpicc`, which is what mpi-sys uses.
+fn mpicc_mpi_impl() -> Result<MpiImpl, String> {
+    let mpicc = ::env::mpicc();
+
+    // Only Open MPI understands this
+    if let Ok(stdout) = run_for_stdout(Command::new(&mpicc).arg("--showme:version")) {
+        if stdout.contains("Open MPI") {
+            return Ok(MpiImpl::OpenMpi);
+        }
+    }
+
+    // MPICH and friends
+    let stdout = run_for_stdout(Command::new(&mpicc).arg("-show"))?;
+    mpi_impl_from_text(&stdout).ok_or_else(|| {
+        format!("could not identify the MPI implementation from `{} -show`: {}", mpicc, stdout.trim())
+    })
+}
+
+// Guess an implementation from e.g. a library path or a compiler command line.
+fn mpi_impl_from_text(text: &str) -> Option<MpiImpl> {
+    let text = text.to_lowercase();
+    if text.contains("openmpi") || text.contains("open-mpi") {
+        Some(MpiImpl::OpenMpi)
+    } else if text.contains("mpich") || text.contains("intel") {
+        Some(MpiImpl::Mpich)
+    } else {
+        None
+    }
+}
+
+fn run_for_stdout(cmd: &mut Command) -> Result<String, String> {
+    let output = cmd.output().map_err(|e| format!("could not run {:?}: {}", cmd, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("{:?} failed: {}", cmd, stderr.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -10,11 +10,12 @@ const SUBMODULE_PATH: &'static str = "lammps";
 
 // ----------------------------------------------------
 
-/// Build lammps from source and emit linker flags
-pub(crate) fn build_from_source_and_link() -> PanicResult<BuildMeta> {
-    let lmp_dir = lammps_repo_dir_build_copy()?;
+/// Build lammps from source
+pub(crate) fn build_from_source() -> PanicResult<BuildMeta> {
+    let revision = ::revisions::selected();
+    let lmp_dir = lammps_build_dir(revision)?;
 
-    let mut cmake = ::cmake::Config::new(lammps_cmake_root()?);
+    let mut cmake = ::cmake::Config::new(lammps_cmake_root(revision)?);
     let mut defines = CcFlags(vec![]);
     let mut include_dirs = CcFlags(vec![]);
 
@@ -32,9 +33,15 @@ pub(crate) fn build_from_source_and_link() -> PanicResult<BuildMeta> {
     //  NOTE: This was all on stable_22Aug2018, and might be fixed on patch_31Aug2018;
     //  I haven't tested yet.)
     cmake.define("BUILD_SHARED_LIBS", "yes");
-    cmake.define("BUILD_EXE", "no");
+    if cfg!(feature = "exe") {
+        cmake.define("BUILD_EXE", "yes");
+        // so that the installed executable can find the installed library
+        cmake.define("CMAKE_INSTALL_RPATH", ::env::out_dir().join("lib").as_path());
+    } else {
+        cmake.define("BUILD_EXE", "no");
+    }
 
-    for key in ::packages::cmake_flags_from_features() {
+    for key in revision.cmake_package_flags()? {
         cmake.define(key, "yes");
     }
 
@@ -51,9 +58,25 @@ pub(crate) fn build_from_source_and_link() -> PanicResult<BuildMeta> {
         defines.0.push(CcFlag::Define("LAMMPS_EXCEPTIONS".into()));
     }
 
-    let lib_dir = PathDir::new(cmake.build())?;
-    println!("cargo:rustc-link-search=native={}/lib", lib_dir.display());
-    println!("cargo:rustc-link-lib=lammps");
+    configure_cmake_target(&mut cmake);
+    ::toolchain::check_cmake_compiler(&mut cmake);
+    configure_cmake_build_tool(&mut cmake);
+
+    let install_dir = PathDir::new(cmake.build())?;
+    let lib_dir = PathDir::new(install_dir.join("lib"))?;
+    let link_flags = CcFlags(vec![
+        CcFlag::LibDir(lib_dir.clone().into()),
+        CcFlag::Lib("lammps".into()),
+    ]);
+    let lib_file = {
+        ["so", "dylib"].iter()
+            .filter_map(|ext| PathFile::new(lib_dir.join(format!("liblammps.{}", ext))).ok())
+            .next()
+    };
+    let exe_file = match cfg!(feature = "exe") {
+        true => Some(PathFile::new(install_dir.join("bin").join("lmp"))?),
+        false => None,
+    };
 
 //  // FIXME: Does this cause problems for other crates that need libstdc++?
 //  //        Should there be a stdcpp-sys crate just for this?
@@ -62,14 +85,119 @@ pub(crate) fn build_from_source_and_link() -> PanicResult<BuildMeta> {
 
     include_dirs.0.push(CcFlag::IncludeDir(lmp_dir.into()));
     Ok(BuildMeta {
+        source: "build",
         header: "src/library.h",
         include_dirs,
         defines,
+        compile_flags: CcFlags(vec![]),
+        link_flags,
+        lib_file,
+        exe_file,
+    })
+}
+
+/// Use the header from the lammps submodule, without building or linking anything.
+///
+/// The resulting crate can be type-checked and documented, but not linked.
+pub(crate) fn headers_only() -> PanicResult<BuildMeta> {
+    let lmp_dir = lammps_headers_dir();
+    if !lmp_dir.join("src").join("library.h").exists() {
+        panic!("could not find lammps/src/library.h, you probably forgot to `git submodule update --init`");
+    }
+
+    let mut defines = CcFlags(vec![]);
+    if cfg!(feature = "exceptions") {
+        defines.0.push(CcFlag::Define("LAMMPS_EXCEPTIONS".into()));
+    }
+
+    Ok(BuildMeta {
+        source: "none",
+        header: "src/library.h",
+        include_dirs: CcFlags(vec![CcFlag::IncludeDir(lmp_dir.into())]),
+        defines,
+        compile_flags: CcFlags(vec![]),
+        link_flags: CcFlags(vec![]),
+        lib_file: None,
+        exe_file: None,
     })
 }
 
 // ----------------------------------------------------
 
+/// Tell CMake what it is building for.
+///
+/// `cmake-rs` already picks a compiler for `TARGET` through the `cc` crate, but CMake
+/// itself also needs to know that it is cross-compiling or else it will go looking
+/// for libraries (MPI, FFTW, ...) on the host.
+fn configure_cmake_target(cmake: &mut ::cmake::Config) {
+    if let Some(toolchain_file) = ::env::cmake_toolchain_file() {
+        // The toolchain file knows better than we do.  (this also stops cmake-rs
+        // from setting CMAKE_<LANG>_COMPILER)
+        cmake.define("CMAKE_TOOLCHAIN_FILE", toolchain_file.as_path());
+        return;
+    }
+
+    if let Some(cc) = ::env::target_compiler("CC") {
+        cmake.define("CMAKE_C_COMPILER", cc);
+    }
+    if let Some(cxx) = ::env::target_compiler("CXX") {
+        cmake.define("CMAKE_CXX_COMPILER", cxx);
+    }
+
+    if !::env::is_cross() {
+        return;
+    }
+
+    let target = ::env::target();
+    let arch = target.split('-').next().unwrap();
+    let system_name = match () {
+        _ if target.contains("-linux") => Some("Linux"),
+        _ if target.contains("-apple-darwin") => Some("Darwin"),
+        _ if target.contains("-freebsd") => Some("FreeBSD"),
+        // cmake-rs takes care of windows
+        _ => None,
+    };
+    if let Some(system_name) = system_name {
+        cmake.define("CMAKE_SYSTEM_NAME", system_name);
+    }
+    cmake.define("CMAKE_SYSTEM_PROCESSOR", arch);
+
+    if let Some(sysroot) = ::env::sysroot() {
+        cmake.define("CMAKE_SYSROOT", sysroot.as_path());
+        cmake.define("CMAKE_FIND_ROOT_PATH", sysroot.as_path());
+        // Programs run during the build; libraries and headers link into the target.
+        cmake.define("CMAKE_FIND_ROOT_PATH_MODE_PROGRAM", "NEVER");
+        cmake.define("CMAKE_FIND_ROOT_PATH_MODE_LIBRARY", "ONLY");
+        cmake.define("CMAKE_FIND_ROOT_PATH_MODE_INCLUDE", "ONLY");
+    }
+}
+
+/// Settings that only affect how fast the build is.
+fn configure_cmake_build_tool(cmake: &mut ::cmake::Config) {
+    if let Some(launcher) = ::env::compiler_launcher() {
+        cmake.define("CMAKE_C_COMPILER_LAUNCHER", &launcher);
+        cmake.define("CMAKE_CXX_COMPILER_LAUNCHER", &launcher);
+    }
+
+    let generator = ::env::cmake_generator();
+    if let Some(ref generator) = generator {
+        cmake.generator(generator);
+    }
+
+    // Cargo tells us how many jobs we may use.
+    if let Some(jobs) = ::env::num_jobs() {
+        // (understood by `cmake --build` since CMake 3.12, for all generators)
+        cmake.env("CMAKE_BUILD_PARALLEL_LEVEL", &jobs);
+        // Older versions of CMake ignore that, so tell Ninja directly; it would otherwise
+        // use every core.  (make already shares cargo's jobserver)
+        if let Some(true) = generator.map(|g| g.contains("Ninja")) {
+            cmake.build_arg(format!("-j{}", jobs));
+        }
+    }
+}
+
+// ----------------------------------------------------
+
 /// HACK:
 /// See https://users.rust-lang.org/t/cargo-exclude-all-contents-of-a-directory-but-keep-the-directory/28137
 ///
@@ -135,6 +263,25 @@ fn lammps_repo_dir_build_copy() -> BoxResult<PathDir> {
     Ok(copy)
 }
 
+/// The source tree of the selected revision, for use in building.
+fn lammps_build_dir(revision: &::revisions::Revision) -> BoxResult<PathDir> {
+    match revision.is_submodule() {
+        true => lammps_repo_dir_build_copy(),
+        false => revision.extract_archive(),
+    }
+}
+
+/// The source tree of the selected revision, for reading headers.
+///
+/// Unlike `lammps_build_dir`, this doesn't copy the submodule.
+pub(crate) fn lammps_headers_dir() -> PathDir {
+    let revision = ::revisions::selected();
+    match revision.is_submodule() {
+        true => lammps_repo_dir(),
+        false => revision.extract_archive().unwrap_or_else(|e| panic!("{}", e)),
+    }
+}
+
 pub(crate) fn lammps_repo_dir() -> PathDir {
     // This library might do bad things if lmp_dir is a symlink,
     // due to path canonicalization...
@@ -172,11 +319,11 @@ pub(crate) fn lammps_dotgit_dir() -> BoxResult<Option<PathDir>> {
     Ok(Some(PathDir::new(path)?))
 }
 
-/// Path to the directory within the lammps submodule that contains CMakeLists.txt.
-pub(crate) fn lammps_cmake_root() -> BoxResult<PathDir> {
+/// Path to the directory within the lammps source that contains CMakeLists.txt.
+fn lammps_cmake_root(revision: &::revisions::Revision) -> BoxResult<PathDir> {
     // NOTE: This first line will succeed even if the submodule isn't initialized because
     //       git still will have created an empty lammps/ directory.
-    let cmake_root = lammps_repo_dir_build_copy()?.join("cmake");
+    let cmake_root = lammps_build_dir(revision)?.join("cmake");
     Ok(PathDir::new(cmake_root.canonicalize().map_err(|_| {
         format!("could not resolve {:?}, you probably forgot to `git submodule update --init`", cmake_root)
     })?)?)
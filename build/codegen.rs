@@ -0,0 +1,309 @@
+// post-processing of the code generated by bindgen
+
+/// Name of the opaque type defined in `src/lib.rs` for LAMMPS instances.
+const INSTANCE_TYPE: &'static str = "lammps_instance";
+
+/// Functions whose first argument is a `void *` that is NOT a LAMMPS instance.
+const NOT_INSTANCE_FIRST_ARG: &'static [&'static str] = &[
+    "lammps_free",
+    "lammps_open",
+    "lammps_open_no_mpi",
+];
+
+/// Functions whose last argument is a `void **` that receives a new LAMMPS instance.
+///
+/// (since 2020, these also return the new instance)
+const INSTANCE_OUT_LAST_ARG: &'static [&'static str] = &[
+    "lammps_open",
+    "lammps_open_no_mpi",
+];
+
+/// Functions that only exist in some versions of LAMMPS.
+///
+/// For each one that is found in the bindings, the build script sets `cfg(has_<name>)`
+/// so that the `safe` module can make use of it.
+pub(crate) const OPTIONAL_FNS: &'static [&'static str] = &[
+    "lammps_extract_global_datatype",
+];
+
+// ----------------------------------------------------
+
+/// A `pub fn` declaration found in the generated code.
+pub(crate) struct ExternFn<'a> {
+    pub(crate) name: &'a str,
+    // byte range of the text between the parentheses
+    params_span: (usize, usize),
+    params: Vec<Param<'a>>,
+    // byte range of the return type, if there is one
+    ret_span: Option<(usize, usize)>,
+}
+
+struct Param<'a> {
+    name: &'a str,
+    ty: &'a str,
+}
+
+/// Locate all of the `pub fn lammps_*` declarations in the bindgen output.
+///
+/// This is not a rust parser; it only needs to understand the rather limited
+/// subset of syntax that bindgen emits for function declarations.
+pub(crate) fn extern_fns<'a>(code: &'a str) -> Vec<ExternFn<'a>> {
+    let mut out = vec![];
+    let mut rest_start = 0;
+    while let Some(offset) = code[rest_start..].find("pub fn ") {
+        let name_start = rest_start + offset + "pub fn ".len();
+        rest_start = name_start;
+
+        let name_len = {
+            code[name_start..]
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(code.len() - name_start)
+        };
+        let name = &code[name_start..name_start + name_len];
+        if !name.starts_with("lammps") {
+            continue;
+        }
+
+        let after_name = name_start + name_len;
+        let open = match code[after_name..].find(|c: char| !c.is_whitespace()) {
+            Some(i) if code[after_name + i..].starts_with('(') => after_name + i,
+            _ => continue,
+        };
+        let close = match find_closing_paren(code, open) {
+            Some(close) => close,
+            None => continue,
+        };
+
+        let params_span = (open + 1, close);
+        let params = {
+            split_top_level_commas(&code[params_span.0..params_span.1]).into_iter()
+                .filter_map(|param| {
+                    let colon = param.find(':')?;
+                    let name = param[..colon].trim();
+                    let ty = param[colon + 1..].trim();
+                    Some(Param { name, ty })
+                })
+                .collect()
+        };
+
+        // "-> *mut ::std::os::raw::c_void;"
+        let ret_span = {
+            let after_close = close + 1;
+            let trimmed = code[after_close..].trim_start();
+            match trimmed.starts_with("->") {
+                true => {
+                    let start = code.len() - trimmed.len() + "->".len();
+                    code[start..].find(';').map(|len| (start, start + len))
+                },
+                false => None,
+            }
+        };
+
+        out.push(ExternFn { name, params_span, params, ret_span });
+        rest_start = close;
+    }
+    out
+}
+
+/// The members of `OPTIONAL_FNS` that are declared in the generated code.
+pub(crate) fn optional_fns_present(code: &str) -> Vec<&'static str> {
+    let present: Vec<_> = extern_fns(code).into_iter().map(|func| func.name).collect();
+    OPTIONAL_FNS.iter().cloned().filter(|name| present.contains(name)).collect()
+}
+
+/// Replace the untyped pointers used for LAMMPS instances with pointers
+/// to the opaque `lammps_instance` type.
+///
+/// This is ABI-compatible with the original declarations.
+pub(crate) fn rewrite_instance_pointers(code: &str) -> String {
+    let instance_ptr = format!("*mut {}", INSTANCE_TYPE);
+    let instance_ptr_ptr = format!("*mut *mut {}", INSTANCE_TYPE);
+
+    let mut out = String::with_capacity(code.len());
+    let mut copied_up_to = 0;
+    for func in extern_fns(code) {
+        let mut params: Vec<(&str, &str)> = {
+            func.params.iter().map(|p| (p.name, p.ty)).collect()
+        };
+
+        if !NOT_INSTANCE_FIRST_ARG.contains(&func.name) {
+            if let Some(first) = params.first_mut() {
+                if is_void_ptr(first.1) {
+                    first.1 = &instance_ptr[..];
+                }
+            }
+        }
+
+        if INSTANCE_OUT_LAST_ARG.contains(&func.name) {
+            if let Some(last) = params.last_mut() {
+                if is_void_ptr_ptr(last.1) {
+                    last.1 = &instance_ptr_ptr[..];
+                }
+            }
+        }
+
+        let (start, end) = func.params_span;
+        out.push_str(&code[copied_up_to..start]);
+        out.push_str(&{
+            params.iter()
+                .map(|&(name, ty)| format!("{}: {}", name, ty))
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+        copied_up_to = end;
+
+        if INSTANCE_OUT_LAST_ARG.contains(&func.name) {
+            if let Some((start, end)) = func.ret_span {
+                if is_void_ptr(&code[start..end]) {
+                    out.push_str(&code[copied_up_to..start]);
+                    out.push(' ');
+                    out.push_str(&instance_ptr);
+                    copied_up_to = end;
+                }
+            }
+        }
+    }
+    out.push_str(&code[copied_up_to..]);
+    out
+}
+
+// ----------------------------------------------------
+
+// Bindgen's output may or may not have been run through rustfmt, so types are
+// compared with all whitespace removed.
+fn normalize_type(ty: &str) -> String {
+    ty.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+fn is_void_ptr(ty: &str) -> bool {
+    normalize_type(ty) == "*mut::std::os::raw::c_void"
+}
+
+fn is_void_ptr_ptr(ty: &str) -> bool {
+    normalize_type(ty) == "*mut*mut::std::os::raw::c_void"
+}
+
+// Given the index of a '(', find the index of the matching ')'.
+fn find_closing_paren(code: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in code[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            },
+            _ => {},
+        }
+    }
+    None
+}
+
+// Split a parameter list on the commas that are not nested inside
+// parentheses or generic arguments (e.g. in function pointer types).
+fn split_top_level_commas(s: &str) -> Vec<&str> {
+    let mut out = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut prev = '\0';
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' | '<' => depth += 1,
+            ')' => depth -= 1,
+            // don't get fooled by the arrow in 'fn() -> T'
+            '>' if prev != '-' => depth -= 1,
+            ',' if depth == 0 => {
+                out.push(&s[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+        prev = c;
+    }
+    if !s[start..].trim().is_empty() {
+        out.push(&s[start..]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (as emitted by bindgen without rustfmt, except for the line breaks)
+    const BINDINGS: &str = "\
+extern \"C\" {
+    pub fn lammps_open_no_mpi(arg1: ::std::os::raw::c_int, arg2: *mut *mut ::std::os::raw::c_char, arg3: *mut *mut ::std::os::raw::c_void);
+}
+extern \"C\" {
+    pub fn lammps_close(arg1: *mut ::std::os::raw::c_void);
+}
+extern \"C\" {
+    pub fn lammps_extract_global(
+        arg1: *mut ::std::os::raw::c_void,
+        arg2: *mut ::std::os::raw::c_char,
+    ) -> *mut ::std::os::raw::c_void;
+}
+extern \"C\" {
+    pub fn lammps_free(arg1: *mut ::std::os::raw::c_void);
+}
+extern \"C\" {
+    pub fn lammps_set_fix_external_callback(arg1: *mut ::std::os::raw::c_void, arg2: *mut ::std::os::raw::c_char, arg3: ::std::option::Option<unsafe extern \"C\" fn(arg1: *mut ::std::os::raw::c_void, arg2: ::std::os::raw::c_int) -> f64>, arg4: *mut ::std::os::raw::c_void);
+}
+extern \"C\" {
+    pub fn lammps_extract_global_datatype(arg1: *mut ::std::os::raw::c_void, name: *const ::std::os::raw::c_char) -> ::std::os::raw::c_int;
+}
+pub fn not_lammps(arg1: *mut ::std::os::raw::c_void);
+";
+
+    #[test]
+    fn finds_fns() {
+        let fns = extern_fns(BINDINGS);
+        let names: Vec<_> = fns.iter().map(|func| func.name).collect();
+        assert_eq!(names, vec![
+            "lammps_open_no_mpi", "lammps_close", "lammps_extract_global", "lammps_free",
+            "lammps_set_fix_external_callback", "lammps_extract_global_datatype",
+        ]);
+
+        let params = |name| {
+            let func = fns.iter().find(|func| func.name == name).unwrap();
+            func.params.iter().map(|p| (p.name, p.ty)).collect::<Vec<_>>()
+        };
+        assert_eq!(params("lammps_extract_global"), vec![
+            ("arg1", "*mut ::std::os::raw::c_void"),
+            ("arg2", "*mut ::std::os::raw::c_char"),
+        ]);
+        // the commas inside the function pointer type don't split it
+        assert_eq!(params("lammps_set_fix_external_callback").len(), 4);
+    }
+
+    #[test]
+    fn finds_optional_fns() {
+        assert_eq!(optional_fns_present(BINDINGS), vec!["lammps_extract_global_datatype"]);
+        assert_eq!(optional_fns_present(&BINDINGS.replace("_datatype", "_kind")), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn rewrites_instances() {
+        let code = rewrite_instance_pointers(BINDINGS);
+        assert!(code.contains("pub fn lammps_open_no_mpi(arg1: ::std::os::raw::c_int, arg2: *mut *mut ::std::os::raw::c_char, arg3: *mut *mut lammps_instance);"));
+        assert!(code.contains("pub fn lammps_close(arg1: *mut lammps_instance);"));
+        // the return type is not an instance, and the line breaks are lost
+        assert!(code.contains("pub fn lammps_extract_global(arg1: *mut lammps_instance, arg2: *mut ::std::os::raw::c_char) -> *mut ::std::os::raw::c_void;"));
+        assert!(code.contains("pub fn lammps_free(arg1: *mut ::std::os::raw::c_void);"));
+        // only the first argument of the function itself
+        assert!(code.contains("pub fn lammps_set_fix_external_callback(arg1: *mut lammps_instance, arg2: *mut ::std::os::raw::c_char, arg3: ::std::option::Option<unsafe extern \"C\" fn(arg1: *mut ::std::os::raw::c_void, arg2: ::std::os::raw::c_int) -> f64>, arg4: *mut ::std::os::raw::c_void);"));
+        assert!(code.contains("pub fn not_lammps(arg1: *mut ::std::os::raw::c_void);"));
+    }
+
+    #[test]
+    fn rewrites_open_return() {
+        let code = "pub fn lammps_open(argc: c_int, argv: *mut *mut c_char, comm: MPI_Comm, ptr: *mut *mut ::std::os::raw::c_void) -> *mut ::std::os::raw::c_void;";
+        assert_eq!(
+            rewrite_instance_pointers(code),
+            "pub fn lammps_open(argc: c_int, argv: *mut *mut c_char, comm: MPI_Comm, ptr: *mut *mut lammps_instance) -> *mut lammps_instance;",
+        );
+    }
+}
@@ -19,10 +19,15 @@ macro_rules! vec_from_features {
 mod packages;
 mod probe;
 mod build;
+mod codegen;
+mod audit;
+mod tree;
+mod toolchain;
+mod revisions;
 
 // ----------------------------------------------------
 
-use ::path_abs::{PathArc, PathDir, FileRead};
+use ::path_abs::{PathArc, PathDir, PathFile, FileRead};
 type BoxResult<T> = Result<T, Box<dyn std::error::Error>>;
 use ::walkdir::WalkDir;
 
@@ -38,24 +43,43 @@ use ::std::borrow::Borrow;
 fn main() -> PanicResult<()> {
     _main_print_reruns()?;
 
-    let meta = _main_link_library()?;
+    let (meta, code) = _main_find_library()?;
 
-    _main_gen_bindings(meta)?;
+    _main_print_link_flags(&meta);
+    _main_print_metadata(&meta);
+    _main_write_bindings(&code)?;
+    _main_print_cfgs(&meta, &code);
+    _main_write_build_info(&meta)?;
 
     Ok(())
 }
 
-fn _main_link_library() -> PanicResult<BuildMeta> {
+fn _main_find_library() -> PanicResult<(BuildMeta, String)> {
     match ::env::mode() {
         Mode::Auto => {
-            if let Ok(meta) = probe::probe_and_link() {
-                return Ok(meta);
-            } else {
-                Ok(build::build_from_source_and_link()?)
+            // A system library that fails the symbol audit is treated just like one that
+            // couldn't be found.
+            let from_system = {
+                probe::probe().map_err(|e| e.to_string())
+                    .and_then(|meta| gen_audited_bindings(meta).map_err(|e| e.to_string()))
+            };
+            match from_system {
+                Ok(found) => Ok(found),
+                Err(e) => {
+                    // Say why, in case the user expected the system library to be used.
+                    for line in e.lines() {
+                        println!("cargo:warning=not using system lammps: {}", line);
+                    }
+                    println!("cargo:warning=building lammps from source instead");
+                    Ok(gen_audited_bindings(build::build_from_source()?)?)
+                },
             }
         },
-        Mode::BuildOnly => Ok(build::build_from_source_and_link()?),
-        Mode::SystemOnly => Ok(probe::probe_and_link()?),
+        Mode::BuildOnly => Ok(gen_audited_bindings(build::build_from_source()?)?),
+        Mode::SystemOnly => Ok(gen_audited_bindings(probe::probe()?)?),
+        Mode::BuildTree(dir) => Ok(gen_audited_bindings(tree::link_build_tree(&dir)?)?),
+        // (there's no library to audit)
+        Mode::CheckOnly => Ok(gen_audited_bindings(build::headers_only()?)?),
     }
 }
 
@@ -63,35 +87,49 @@ fn _main_link_library() -> PanicResult<BuildMeta> {
 
 // Information discovered during the build that is needed during bindgen.
 struct BuildMeta {
+    // Where the library came from; one of "system", "build", "build-dir" or "none".
+    source: &'static str,
     // Path for an #include directive.
     header: &'static str,
     // A bunch of -I arguments
     include_dirs: CcFlags,
     // A bunch of -D arguments
     defines: CcFlags,
+    // Any other arguments for the C compiler, like -pthread.
+    compile_flags: CcFlags,
+    // A bunch of -L and -l arguments, and anything else for the linker.
+    //
+    // These are only given to cargo once we have settled on a library.
+    link_flags: CcFlags,
+    // The library file that will be linked, if known.
+    lib_file: Option<PathFile>,
+    // The `lmp` executable, if the "exe" feature is enabled.
+    exe_file: Option<PathFile>,
 }
 
 // ----------------------------------------------------
 
-fn _main_gen_bindings(meta: BuildMeta) -> PanicResult<()> {
-    let BuildMeta { header, mut include_dirs, defines } = meta;
+fn gen_audited_bindings(meta: BuildMeta) -> Result<(BuildMeta, String), audit::AuditError> {
+    let code = gen_bindings(&meta);
+    audit::audit_symbols(&meta, &code)?;
+    audit::audit_mpi(&meta)?;
+    Ok((meta, code))
+}
 
-    let lmp_dir = ::build::lammps_repo_dir();
-    let out_path = env::out_dir();
-
-    let _ = ::std::fs::create_dir(out_path.join("codegen"));
+fn gen_bindings(meta: &BuildMeta) -> String {
+    let lmp_dir = ::build::lammps_headers_dir();
 
     let mut gen = ::bindgen::Builder::default();
     gen = gen.header_contents(
         "include_lammps.h",
-        &format!(r##"#include <{}>"##, header),
+        &format!(r##"#include <{}>"##, meta.header),
     );
 
     // let bindgen find the mpi.h from the "MPI STUBS" library.
     //
     // It doesn't matter whether or not this is what LAMMPS was built against, since we
     // won't be exposing the relevant bindings.
-    include_dirs.0.push(CcFlag::IncludeDir(lmp_dir.join("src").join("STUBS").into()));
+    let stubs_dir = CcFlag::IncludeDir(lmp_dir.join("src").join("STUBS").into());
 
     // HACK: Thanks to https://github.com/rust-lang/cargo/issues/5237
     //       we cannot update our bindgen dependency to a version with `blacklist_function`
@@ -99,22 +137,140 @@ fn _main_gen_bindings(meta: BuildMeta) -> PanicResult<()> {
     //       v0.31, we can use `blacklist_type` to accomplish the same effect.
     gen = gen.blacklist_type("lammps_open");
 
-    gen = gen.clang_args(defines.to_args());
-    gen = gen.clang_args(include_dirs.to_args());
+    gen = gen.clang_args(meta.defines.to_args());
+    gen = gen.clang_args(meta.include_dirs.to_args());
+    gen = gen.clang_args(meta.compile_flags.to_args());
+    gen = gen.clang_arg(WithoutSpace(&stubs_dir).to_string());
+    gen = gen.clang_args(cross_clang_flags().to_args());
 
     // support older versions of libclang, which will mangle even
     // the names of C functions unless we disable this.
     gen = gen.trust_clang_mangling(false);
     gen = gen.whitelist_function("lammps.*");
 
-    gen.generate()
-        .expect("Unable to generate bindings for 'lammps'!")
-        .write_to_file(out_path.join("codegen/lammps.rs"))
+    let code = gen.generate().expect("Unable to generate bindings for 'lammps'!").to_string();
+
+    // Replace the `void *`s for LAMMPS instances with a dedicated opaque type.
+    codegen::rewrite_instance_pointers(&code)
+}
+
+fn _main_write_bindings(code: &str) -> PanicResult<()> {
+    let out_path = env::out_dir();
+    let _ = ::std::fs::create_dir(out_path.join("codegen"));
+
+    ::std::fs::write(out_path.join("codegen/lammps.rs"), code)
         .expect("Couldn't write bindings for 'lammps'!");
+    Ok(())
+}
 
+// cfgs describing the version and configuration of LAMMPS, for the `safe` module.
+fn _main_print_cfgs(meta: &BuildMeta, code: &str) {
+    // LAMMPS_SIZES, which determines the sizes of tagint and imageint.
+    // (the default, smallbig, has no define)
+    for &define in &["LAMMPS_BIGBIG", "LAMMPS_SMALLSMALL"] {
+        let cfg = define.to_lowercase();
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        if meta.defines.0.contains(&CcFlag::Define(define.to_string())) {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+    }
+
+    let present = codegen::optional_fns_present(code);
+    for &name in codegen::OPTIONAL_FNS {
+        println!("cargo:rustc-check-cfg=cfg(has_{})", name);
+        if present.contains(&name) {
+            println!("cargo:rustc-cfg=has_{}", name);
+        }
+    }
+}
+
+// Metadata for the build scripts of crates that depend on us. (DEP_LAMMPS_*)
+fn _main_print_metadata(meta: &BuildMeta) {
+    if let Some(ref exe_file) = meta.exe_file {
+        println!("cargo:exe={}", exe_file.display());
+    }
+}
+
+// Record what we linked, for `lammps_sys::BUILD_INFO`.
+fn _main_write_build_info(meta: &BuildMeta) -> PanicResult<()> {
+    let library = meta.lib_file.as_ref().map(|f| f.display().to_string());
+    let exe = meta.exe_file.as_ref().map(|f| f.display().to_string());
+    let packages: Vec<_> = {
+        // (RUST_LAMMPS_VERSION forces a source build, so this is right for every source)
+        revisions::selected().cmake_package_flags()?.into_iter()
+            .map(|flag| flag.trim_start_matches("PKG_"))
+            .collect()
+    };
+
+    // (Debug formatting of strings produces valid rust string literals)
+    let code = format!("\
+        BuildInfo {{
+            source: {:?},
+            library: {:?},
+            exe: {:?},
+            packages: &{:?},
+        }}
+    ", meta.source, library, exe, packages);
+
+    let out_path = env::out_dir();
+    ::std::fs::write(out_path.join("codegen/build_info.rs"), code)
+        .expect("Couldn't write build info!");
     Ok(())
 }
 
+fn _main_print_link_flags(meta: &BuildMeta) {
+    let mut link_args = vec![];
+    for flag in &meta.link_flags.0 {
+        match *flag {
+            CcFlag::LibDir(ref path) => println!("cargo:rustc-link-search=native={}", path.display()),
+            CcFlag::Lib(ref name) => println!("cargo:rustc-link-lib={}", name),
+            CcFlag::StaticLib(ref name) => println!("cargo:rustc-link-lib=static={}", name),
+            CcFlag::LinkArg(ref arg) => link_args.push(arg.clone()),
+            CcFlag::Driver(ref arg) => link_args.push(driver_flag_for_linker(arg)),
+            _ => panic!("unexpected link flag: {}", WithoutSpace(flag)),
+        }
+    }
+
+    // NOTE: cargo only applies these to this crate's own examples and tests, and not to
+    //       crates that depend on us.  We republish them as DEP_LAMMPS_LINK_ARGS so that
+    //       build scripts of dependent crates can forward them if necessary.
+    for arg in &link_args {
+        println!("cargo:rustc-link-arg={}", arg);
+    }
+    if !link_args.is_empty() {
+        // Separated like CARGO_ENCODED_RUSTFLAGS, since args may contain spaces.
+        println!("cargo:link_args={}", link_args.join("\x1f"));
+    }
+}
+
+// LAMMPS may have been compiled with a different compiler than the one rustc will link
+// with, and Intel's OpenMP flag is not understood by the others.
+fn driver_flag_for_linker(arg: &str) -> String {
+    if arg != "-qopenmp" {
+        return arg.to_string();
+    }
+    let linker = env::linker();
+    match toolchain::identify(&linker) {
+        Ok(ref compiler) if compiler.kind == toolchain::CompilerKind::Intel => arg.to_string(),
+        _ => String::from("-fopenmp"),
+    }
+}
+
+// Flags that make clang parse the headers as they will be seen by the target.
+//
+// Without these, a cross build would get bindings for the host's type sizes
+// and the host's system headers.
+fn cross_clang_flags() -> CcFlags {
+    let mut flags = CcFlags(vec![]);
+    if env::is_cross() {
+        flags.0.push(CcFlag::Other(format!("--target={}", env::target())));
+    }
+    if let Some(sysroot) = env::sysroot() {
+        flags.0.push(CcFlag::Other(format!("--sysroot={}", sysroot.display())));
+    }
+    flags
+}
+
 // ----------------------------------------------------
 
 fn _main_print_reruns() -> PanicResult<()> {
@@ -179,6 +335,10 @@ pub enum Mode {
     Auto,
     SystemOnly,
     BuildOnly,
+    // Link an existing (uninstalled) CMake build directory.
+    BuildTree(PathDir),
+    // Don't link anything.  (for cargo check, docs.rs...)
+    CheckOnly,
 }
 
 mod env {
@@ -188,15 +348,160 @@ mod env {
 
     pub fn mode() -> Mode {
         let var = "RUST_LAMMPS_SOURCE";
-        let value = get_rerun_nonempty(var).unwrap_or_else(|| String::from("auto"));
-        match &value[..] {
+        let value = get_rerun_nonempty(var);
+
+        // An installed library could be any version, so we can only honor this by building.
+        let version = get_rerun_nonempty("RUST_LAMMPS_VERSION");
+        // Likewise, we can't get a matching `lmp` from an installed library.
+        let must_build = version.is_some() || cfg!(feature = "exe");
+
+        if let Some(dir) = get_rerun_nonempty("RUST_LAMMPS_BUILD_DIR") {
+            if version.is_some() {
+                panic!("RUST_LAMMPS_BUILD_DIR cannot be used with RUST_LAMMPS_VERSION");
+            }
+            match value.as_ref().map(|s| &s[..]) {
+                None | Some("auto") => {},
+                Some(s) => panic!("RUST_LAMMPS_BUILD_DIR cannot be used with RUST_LAMMPS_SOURCE={}", s),
+            }
+            let dir = PathDir::new(&dir).unwrap_or_else(|e| panic!("RUST_LAMMPS_BUILD_DIR: {}", e));
+            return Mode::BuildTree(dir);
+        }
+
+        // docs.rs has no LAMMPS and wouldn't want to wait for one to build
+        let default = match get_rerun("DOCS_RS") {
+            Some(_) => "none",
+            None => "auto",
+        };
+        match &value.unwrap_or_else(|| String::from(default))[..] {
+            "auto" if must_build => Mode::BuildOnly,
+            "system" if must_build => {
+                panic!("RUST_LAMMPS_SOURCE=system cannot be used with RUST_LAMMPS_VERSION or the `exe` feature")
+            },
             "auto" => Mode::Auto,
             "system" => Mode::SystemOnly,
             "build" => Mode::BuildOnly,
+            "none" => Mode::CheckOnly,
             s => panic!("Bad value for RUST_LAMMPS_SOURCE: {}", s),
         }
     }
 
+    /// `RUST_LAMMPS_VERSION`, the tag of a LAMMPS release to build instead of the submodule.
+    pub fn lammps_version() -> Option<String> {
+        get_rerun_nonempty("RUST_LAMMPS_VERSION")
+    }
+
+    /// `RUST_LAMMPS_ARCHIVE_DIR`, where to find source archives for `RUST_LAMMPS_VERSION`.
+    pub fn archive_dir() -> Option<PathDir> {
+        get_rerun_nonempty("RUST_LAMMPS_ARCHIVE_DIR").map(|s| {
+            PathDir::new(&s).unwrap_or_else(|e| panic!("RUST_LAMMPS_ARCHIVE_DIR: {}", e))
+        })
+    }
+
+    /// `RUST_LAMMPS_MACHINE`, the `LAMMPS_MACHINE` suffix of a system library.
+    pub fn machine() -> Option<String> {
+        get_rerun_nonempty("RUST_LAMMPS_MACHINE")
+    }
+
+    /// `RUST_LAMMPS_CMAKE_TOOLCHAIN_FILE`, for cross-compiling the source build.
+    pub fn cmake_toolchain_file() -> Option<PathFile> {
+        get_rerun_nonempty("RUST_LAMMPS_CMAKE_TOOLCHAIN_FILE").map(|s| {
+            PathFile::new(&s).unwrap_or_else(|e| panic!("RUST_LAMMPS_CMAKE_TOOLCHAIN_FILE: {}", e))
+        })
+    }
+
+    /// `RUST_LAMMPS_COMPILER_CHECK`, what to do if the C++ compiler has a known issue.
+    pub fn compiler_check() -> toolchain::CheckMode {
+        let var = "RUST_LAMMPS_COMPILER_CHECK";
+        match get_rerun_nonempty(var).as_ref().map(|s| &s[..]) {
+            None | Some("warn") => toolchain::CheckMode::Warn,
+            Some("error") => toolchain::CheckMode::Error,
+            Some("fallback") => toolchain::CheckMode::Fallback,
+            Some(s) => panic!("Bad value for {}: {}", var, s),
+        }
+    }
+
+    /// A program like `ccache` or `sccache` to wrap the C and C++ compilers in source builds.
+    ///
+    /// Taken from `RUST_LAMMPS_COMPILER_LAUNCHER`, else `RUSTC_WRAPPER` if that is sccache
+    /// (which also understands C++).  `RUST_LAMMPS_COMPILER_LAUNCHER=none` disables this.
+    pub fn compiler_launcher() -> Option<String> {
+        match get_rerun_nonempty("RUST_LAMMPS_COMPILER_LAUNCHER").as_ref().map(|s| &s[..]) {
+            Some("none") => return None,
+            Some(s) => return Some(s.to_string()),
+            None => {},
+        }
+        get_rerun_nonempty("RUSTC_WRAPPER").filter(|wrapper| {
+            let stem = Path::new(wrapper).file_stem().and_then(|s| s.to_str());
+            stem == Some("sccache")
+        })
+    }
+
+    /// `RUST_LAMMPS_CMAKE_GENERATOR`, e.g. `"Ninja"`.
+    pub fn cmake_generator() -> Option<String> {
+        get_rerun_nonempty("RUST_LAMMPS_CMAKE_GENERATOR")
+    }
+
+    /// The number of parallel jobs that cargo allows us.
+    pub fn num_jobs() -> Option<String> {
+        env::var("NUM_JOBS").ok()
+    }
+
+    /// The root directory of the target system's filesystem, if cross-compiling.
+    ///
+    /// Taken from `RUST_LAMMPS_SYSROOT`, else from the same variable that `pkg-config` uses.
+    pub fn sysroot() -> Option<PathArc> {
+        get_rerun_nonempty("RUST_LAMMPS_SYSROOT")
+            .or_else(|| get_targeted_rerun_nonempty("PKG_CONFIG_SYSROOT_DIR"))
+            .map(PathArc::new)
+    }
+
+    /// A compiler for the target, if the user explicitly specified one.
+    ///
+    /// `kind` is `"CC"` or `"CXX"`.  The variables are searched in the same order
+    /// as the `cc` crate, e.g. `CXX_aarch64-unknown-linux-gnu`, `CXX_aarch64_unknown_linux_gnu`,
+    /// `TARGET_CXX`, `CXX`.
+    pub fn target_compiler(kind: &str) -> Option<String> {
+        get_targeted_rerun_nonempty(kind)
+    }
+
+    /// The program that rustc will use to link, as far as we can tell.
+    ///
+    /// This is `RUSTC_LINKER` if set, and otherwise the C compiler for the target.
+    pub fn linker() -> String {
+        get_rerun_nonempty("RUSTC_LINKER")
+            .or_else(|| target_compiler("CC"))
+            .unwrap_or_else(|| String::from("cc"))
+    }
+
+    /// The `nm` program for inspecting the target's libraries.
+    pub fn nm() -> String {
+        get_targeted_rerun_nonempty("NM").unwrap_or_else(|| String::from("nm"))
+    }
+
+    /// The `pkg-config` program.  (pkg-config-rs reads the same variable)
+    pub fn pkg_config() -> String {
+        get_rerun_nonempty("PKG_CONFIG").unwrap_or_else(|| String::from("pkg-config"))
+    }
+
+    /// Read a variable that may be given per-target, like `PKG_CONFIG_PATH_<target>`.
+    pub fn targeted(base: &str) -> Option<String> {
+        get_targeted_rerun_nonempty(base)
+    }
+
+    /// Test whether a variable is set (to anything, even an empty string).
+    pub fn is_set(var: &str) -> bool {
+        get_rerun(var).is_some()
+    }
+
+    /// The MPI compiler wrapper, which is also what `mpi-sys` uses to find MPI.
+    pub fn mpicc() -> String {
+        get_rerun_nonempty("MPICC").unwrap_or_else(|| String::from("mpicc"))
+    }
+
+    pub fn target() -> String { expect("TARGET") }
+    pub fn host() -> String { expect("HOST") }
+    pub fn is_cross() -> bool { target() != host() }
+
     pub fn out_dir() -> PathDir {
         PathDir::new(expect("OUT_DIR")).unwrap_or_else(|e| panic!("{}", e))
     }
@@ -207,6 +512,17 @@ mod env {
         env::var(var).unwrap_or_else(|e| panic!("error reading {}: {}", var, e))
     }
 
+    // Look up a variable that may be given per-target, in the same manner as the
+    // `cc` and `pkg-config` crates.
+    fn get_targeted_rerun_nonempty(base: &str) -> Option<String> {
+        let target = target();
+        let kind = if is_cross() { "TARGET" } else { "HOST" };
+        None.or_else(|| get_rerun_nonempty(&format!("{}_{}", base, target)))
+            .or_else(|| get_rerun_nonempty(&format!("{}_{}", base, target.replace("-", "_"))))
+            .or_else(|| get_rerun_nonempty(&format!("{}_{}", kind, base)))
+            .or_else(|| get_rerun_nonempty(base))
+    }
+
     fn get_rerun_nonempty(s: &str) -> Option<String> {
         get_rerun(s).and_then(|s| match &s[..] {
             "" => None,
@@ -232,10 +548,22 @@ pub enum CcFlag {
     Define(String),
     // an "-Ipath/to/include" flag (or "-I" "path/to/include").
     IncludeDir(PathArc),
+    // an "-isystem" "path/to/include" flag.
+    SystemIncludeDir(PathArc),
     // an "-Lpath/to/include" flag (or "-L" "path/to/include").
     LibDir(PathArc),
     // an "-llibrary" flag
     Lib(String),
+    // an "-llibrary" flag for a library that must be linked statically.
+    //
+    // (this distinction only matters to cargo)
+    StaticLib(String),
+    // a flag for the linker, like "-Wl,-rpath,/some/dir".
+    // ("-Xlinker" "arg" is normalized to "-Wl,arg")
+    LinkArg(String),
+    // a flag that must be given to the compiler driver both when compiling and
+    // when linking, like "-pthread" or "-fopenmp".
+    Driver(String),
     // an unknown argument.  We will assume it is not something
     // that would prevent the next argument from being parsed as
     // an option, because there's no reliable way to tell.
@@ -252,12 +580,50 @@ impl CcFlag {
     fn fmt_with_space(&self, space: &str, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CcFlag::IncludeDir(ref path) => write!(f, "-I{}{}", space, path.display()),
+            // (the joined form of this one isn't universally understood)
+            CcFlag::SystemIncludeDir(ref path) => write!(f, "-isystem {}", path.display()),
             CcFlag::LibDir(ref path) => write!(f, "-L{}{}", space, path.display()),
             CcFlag::Lib(ref s) => write!(f, "-l{}{}", space, s),
+            CcFlag::StaticLib(ref s) => write!(f, "-l{}{}", space, s),
             CcFlag::Define(ref s) => write!(f, "-D{}{}", space, s),
+            CcFlag::LinkArg(ref s) => write!(f, "{}", s),
+            CcFlag::Driver(ref s) => write!(f, "{}", s),
             CcFlag::Other(ref s) => write!(f, "{}", s),
         }
     }
+
+    /// Parse a single flag, taking its argument from `rest` if it is a separate word.
+    fn parse<'a, I>(word: &'a str, rest: &mut I) -> CcFlag
+    where I: Iterator<Item=&'a str>,
+    {
+        let mut arg = |prefix: &str| -> String {
+            match &word[prefix.len()..] {
+                "" => rest.next().unwrap_or("").to_string(),
+                s => s.to_string(),
+            }
+        };
+
+        match word {
+            "-pthread" | "-pthreads" | "-qopenmp" => CcFlag::Driver(word.to_string()),
+            _ if word.starts_with("-fopenmp") => CcFlag::Driver(word.to_string()),
+            "-Xlinker" => CcFlag::LinkArg(format!("-Wl,{}", arg("-Xlinker"))),
+            _ if word.starts_with("-Wl,") => CcFlag::LinkArg(word.to_string()),
+            _ if word.starts_with("-isystem") => CcFlag::SystemIncludeDir(PathArc::new(arg("-isystem"))),
+            _ if word.starts_with("-I") => CcFlag::IncludeDir(PathArc::new(arg("-I"))),
+            _ if word.starts_with("-L") => CcFlag::LibDir(PathArc::new(arg("-L"))),
+            _ if word.starts_with("-l") => CcFlag::Lib(arg("-l")),
+            _ if word.starts_with("-D") => CcFlag::Define(arg("-D")),
+            _ => CcFlag::Other(word.to_string()),
+        }
+    }
+
+    // Produce atomic arguments without fear of quoting issues.
+    fn to_args(&self) -> Vec<String> {
+        match *self {
+            CcFlag::SystemIncludeDir(ref path) => vec!["-isystem".into(), path.display().to_string()],
+            _ => vec![WithoutSpace(self).to_string()],
+        }
+    }
 }
 
 // Displays as "-l iberty"
@@ -273,7 +639,7 @@ impl<C> fmt::Display for WithSpace<C> where C: Borrow<CcFlag> {
 // Displays as "-liberty"
 //
 // This format is convenient for producing atomic arguments without fear
-// of quoting issues.
+// of quoting issues.  (except for -isystem; see `CcFlag::to_args`)
 struct WithoutSpace<C>(C);
 impl<C> fmt::Display for WithoutSpace<C> where C: Borrow<CcFlag> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
@@ -282,7 +648,17 @@ impl<C> fmt::Display for WithoutSpace<C> where C: Borrow<CcFlag> {
 
 impl CcFlags {
     fn to_args(&self) -> Vec<String> {
-        self.0.iter().map(|x| WithoutSpace(x).to_string()).collect()
+        self.0.iter().flat_map(|x| x.to_args()).collect()
+    }
+
+    /// Parse a list of words, like those printed by `pkg-config --cflags`.
+    fn parse<S: AsRef<str>>(words: &[S]) -> CcFlags {
+        let mut words = words.iter().map(|s| s.as_ref());
+        let mut flags = vec![];
+        while let Some(word) = words.next() {
+            flags.push(CcFlag::parse(word, &mut words));
+        }
+        CcFlags(flags)
     }
 }
 
@@ -1,8 +1,11 @@
 use ::{BuildMeta, CcFlag, CcFlags};
 use ::std::fmt;
+use ::std::path::{Path, PathBuf};
+use ::std::process::Command;
+use ::path_abs::{PathArc, PathFile};
 
-pub(crate) fn probe_and_link() -> Result<BuildMeta, ProbeError> {
-    probe_and_link_via_pkgconfig()
+pub(crate) fn probe() -> Result<BuildMeta, ProbeError> {
+    probe_via_pkgconfig()
 }
 
 pub(crate) enum ProbeError {
@@ -27,39 +30,351 @@ impl fmt::Display for ProbeError {
 
 // Lammps does offer a cmake-based build system, which appears to be designed to install a
 // `.pc` file for pkgconfig.  We can look for that.
-fn probe_and_link_via_pkgconfig() -> Result<BuildMeta, ProbeError> {
-    let library = ::pkg_config::probe_library("liblammps")?;
-    let include_dirs = CcFlags({
-        library.include_paths.into_iter()
-            .map(Into::into).map(CcFlag::IncludeDir)
-            .collect()
-    });
-    let defines = CcFlags({
-        library.defines.into_iter()
-            .map(|(key, value)| match value {
-                Some(value) => CcFlag::Define(format!("{}={}", key, value)),
-                None => CcFlag::Define(format!("{}", key)),
-            })
-            .collect()
-    });
+fn probe_via_pkgconfig() -> Result<BuildMeta, ProbeError> {
+    prepare_pkg_config_for_target();
+
+    let (pc_name, library) = probe_first_of(&pkg_config_names())?;
+
+    // Make sure that cargo notices when the system library is upgraded or replaced,
+    // since stale bindings could otherwise silently disagree with the installed ABI.
+    let files = resolve_installed_files(&pc_name, &library);
+    for file in files.pc_file.iter().chain(&files.header).chain(&files.lib_file) {
+        ::rerun_if_changed(file.display());
+    }
+
+    // pkg-config-rs only understands a handful of flags, so we ask pkg-config ourselves.
+    let statik = wants_static(&pc_name, &files);
+    let cflags = CcFlags::parse(&pkg_config_words(&pc_name, statik, "--cflags")?);
+    let libs = CcFlags::parse(&pkg_config_words(&pc_name, statik, "--libs")?);
+
+    let mut include_dirs = CcFlags(vec![]);
+    let mut defines = CcFlags(vec![]);
+    let mut compile_flags = CcFlags(vec![]);
+    for flag in cflags.0 {
+        match flag {
+            CcFlag::Define(_) => defines.0.push(flag),
+            CcFlag::IncludeDir(_) |
+            CcFlag::SystemIncludeDir(_) => include_dirs.0.push(flag),
+            _ => compile_flags.0.push(flag),
+        }
+    }
+
+    let mut link_flags = CcFlags(vec![]);
+    for flag in libs.0 {
+        link_flags.0.push(match flag {
+            CcFlag::Lib(ref name) if statik && name.starts_with("lammps") => CcFlag::StaticLib(name.clone()),
+            CcFlag::Lib(_) |
+            CcFlag::LibDir(_) |
+            CcFlag::LinkArg(_) |
+            CcFlag::Driver(_) => flag,
+            // Anything unfamiliar in here is presumably for the linker.
+            CcFlag::Other(s) => CcFlag::LinkArg(s),
+            _ => continue,
+        });
+    }
+    if statik {
+        // rustc won't look in the linker's default directories for a static lib.
+        if let Some(dir) = files.lib_file.as_ref().and_then(|file| file.parent()) {
+            link_flags.0.push(CcFlag::LibDir(PathArc::new(dir)));
+        }
+        // LAMMPS is C++, but an `.a` file doesn't know that.
+        let cpp_runtime = match ::env::target().contains("-apple-") {
+            true => "c++",
+            false => "stdc++",
+        };
+        let cpp_runtime = CcFlag::Lib(cpp_runtime.to_string());
+        if !link_flags.0.contains(&cpp_runtime) {
+            link_flags.0.push(cpp_runtime);
+        }
+    }
 
     if cfg!(feature = "exceptions") {
-        // NOTE: shoving subtleties like "-DLAMMPS_EXCEPTIONS=definition" under the rug.
-        let needle = CcFlag::Define(String::from("LAMMPS_EXCEPTIONS"));
-        if !defines.0.iter().any(|x| x == &needle) {
-            let msg = String::from("\
-                system lammps was built without -DLAMMPS_EXCEPTIONS \
-                (--features=exceptions)\
-            ");
-            return Err(ProbeError::String(msg));
+        match exception_support(&library.version, &defines, files.lib_file.as_ref()) {
+            ExceptionSupport::Defined => {},
+            ExceptionSupport::Detected => {
+                // The header hides the functions behind an #ifdef in older versions.
+                defines.0.push(CcFlag::Define(String::from("LAMMPS_EXCEPTIONS")));
+            },
+            ExceptionSupport::Unsupported => {
+                let msg = String::from("\
+                    system lammps was built without -DLAMMPS_EXCEPTIONS, or it could not \
+                    be confirmed (--features=exceptions). If it does support exceptions, \
+                    add -DLAMMPS_EXCEPTIONS to the Cflags of its .pc file.\
+                ");
+                return Err(ProbeError::String(msg));
+            },
         }
     }
 
     Ok(BuildMeta {
+        source: "system",
         // The CMakeFile thankfully appears to install the header under a sane, unambiguous path.
         // (fortuitously the same one chosen by lammps-sys 0.3.x!)
         header: "lammps/library.h",
         include_dirs,
         defines,
+        compile_flags,
+        link_flags,
+        lib_file: files.lib_file,
+        exe_file: None,
     })
 }
+
+// ----------------------------------------------------
+
+// Names of the .pc files to look for, in order of preference.
+//
+// LAMMPS' CMake build appends `_${LAMMPS_MACHINE}` to the library and .pc file when
+// a machine suffix is set, and distributions commonly ship e.g. both `liblammps_serial`
+// and `liblammps_mpi`.
+fn pkg_config_names() -> Vec<String> {
+    if let Some(machine) = ::env::machine() {
+        return vec![format!("liblammps_{}", machine)];
+    }
+
+    let variant = match cfg!(feature = "mpi") {
+        true => "liblammps_mpi",
+        false => "liblammps_serial",
+    };
+    vec![variant.to_string(), "liblammps".to_string()]
+}
+
+// Returns the first library that pkg-config can find, or the errors for every name.
+fn probe_first_of(names: &[String]) -> Result<(String, ::pkg_config::Library), ProbeError> {
+    let mut errors = vec![];
+    for name in names {
+        let result = {
+            ::pkg_config::Config::new()
+                // emit rerun-if-env-changed for PKG_CONFIG_PATH and friends
+                .env_metadata(true)
+                // we don't want cargo to link anything yet, in case we reject this library
+                .cargo_metadata(false)
+                .probe(name)
+        };
+        match result {
+            Ok(library) => return Ok((name.clone(), library)),
+            Err(e) => errors.push((name, e)),
+        }
+    }
+    match errors.len() {
+        0 => panic!("no names to probe"),
+        1 => Err(errors.pop().unwrap().1.into()),
+        _ => Err(ProbeError::String({
+            errors.iter()
+                .map(|&(name, ref e)| format!("{}: {}", name, e))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })),
+    }
+}
+
+// Decide whether to link statically, in mostly the same manner as pkg-config-rs.
+//
+// Unlike pkg-config-rs, we also link statically if no shared library could be found.
+fn wants_static(pc_name: &str, files: &InstalledFiles) -> bool {
+    let prefix = pc_name.to_uppercase().replace("-", "_");
+    if ::env::is_set(&format!("{}_STATIC", prefix)) {
+        true
+    } else if ::env::is_set(&format!("{}_DYNAMIC", prefix)) {
+        false
+    } else if ::env::is_set("PKG_CONFIG_ALL_STATIC") {
+        true
+    } else if ::env::is_set("PKG_CONFIG_ALL_DYNAMIC") {
+        false
+    } else {
+        files.lib_file.as_ref().and_then(|file| file.extension()) == Some("a".as_ref())
+    }
+}
+
+// Run pkg-config and split its output into words.
+//
+// With `statik`, this includes `Libs.private` (e.g. fftw3, libgomp, libjpeg, libz).
+fn pkg_config_words(pc_name: &str, statik: bool, what: &str) -> Result<Vec<String>, ProbeError> {
+    let mut cmd = Command::new(::env::pkg_config());
+    for &var in &["PKG_CONFIG_PATH", "PKG_CONFIG_LIBDIR", "PKG_CONFIG_SYSROOT_DIR"] {
+        if let Some(value) = ::env::targeted(var) {
+            cmd.env(var, value);
+        }
+    }
+    // pkg-config-rs does this by default
+    cmd.env("PKG_CONFIG_ALLOW_SYSTEM_LIBS", "1");
+    if statik {
+        cmd.arg("--static");
+    }
+    cmd.arg(what).arg(pc_name);
+
+    let output = cmd.output().map_err(|e| ProbeError::String(format!("could not run {:?}: {}", cmd, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ProbeError::String(format!("{:?} failed: {}", cmd, stderr.trim())));
+    }
+    Ok(split_flags(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// pkg-config escapes spaces and other special characters in its output with backslashes.
+fn split_flags(output: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut escaped = false;
+    for c in output.chars() {
+        match c {
+            _ if escaped => {
+                word.push(c);
+                escaped = false;
+            },
+            '\\' => escaped = true,
+            _ if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(word.clone());
+                    word.clear();
+                }
+            },
+            _ => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+// ----------------------------------------------------
+
+// The files of a system lammps installation, as best as we can tell.
+struct InstalledFiles {
+    pc_file: Option<PathFile>,
+    header: Option<PathFile>,
+    lib_file: Option<PathFile>,
+}
+
+fn resolve_installed_files(pc_name: &str, library: &::pkg_config::Library) -> InstalledFiles {
+    let pc_file = {
+        ::pkg_config::get_variable(pc_name, "pcfiledir").ok()
+            .and_then(|dir| PathFile::new(Path::new(&dir).join(format!("{}.pc", pc_name))).ok())
+    };
+
+    // pkg-config omits -I and -L flags for system directories, so those must be checked too.
+    let system_dir = |dir: &str| match ::env::sysroot() {
+        Some(sysroot) => sysroot.join(&dir[1..]).to_path_buf(),
+        None => PathBuf::from(dir),
+    };
+
+    let header = {
+        library.include_paths.iter().cloned()
+            .chain(vec![system_dir("/usr/local/include"), system_dir("/usr/include")])
+            .filter_map(|dir| PathFile::new(dir.join("lammps").join("library.h")).ok())
+            .next()
+    };
+
+    let lib_file = {
+        let lib_dirs = {
+            library.link_paths.iter().cloned()
+                .chain(::pkg_config::get_variable(pc_name, "libdir").ok().map(PathBuf::from))
+                .chain(vec![
+                    system_dir("/usr/local/lib"),
+                    system_dir("/usr/lib"),
+                    system_dir("/usr/lib64"),
+                ])
+                .collect::<Vec<_>>()
+        };
+        let lib_names = library.libs.iter().filter(|name| name.starts_with("lammps"));
+
+        let mut found = None;
+        'search: for name in lib_names {
+            for dir in &lib_dirs {
+                for ext in &["so", "dylib", "a"] {
+                    if let Ok(file) = PathFile::new(dir.join(format!("lib{}.{}", name, ext))) {
+                        found = Some(file);
+                        break 'search;
+                    }
+                }
+            }
+        }
+        found
+    };
+
+    InstalledFiles { pc_file, header, lib_file }
+}
+
+// ----------------------------------------------------
+
+// Versions of LAMMPS from this date onwards are always built with exception support.
+const ALWAYS_HAS_EXCEPTIONS_SINCE: u32 = 20230208;
+
+// Versions of LAMMPS from this date onwards export the exception functions even when
+// built without exceptions, so their symbols prove nothing.
+const ALWAYS_EXPORTS_ERROR_FNS_SINCE: u32 = 20200901;
+
+enum ExceptionSupport {
+    // The .pc file says so.
+    Defined,
+    // The .pc file doesn't say so, but the library is known to support it.
+    Detected,
+    Unsupported,
+}
+
+// Hand-written .pc files frequently leave out -DLAMMPS_EXCEPTIONS, so we can't rely on
+// the defines alone.
+fn exception_support(
+    version: &str,
+    defines: &CcFlags,
+    lib_file: Option<&PathFile>,
+) -> ExceptionSupport {
+    // NOTE: shoving subtleties like "-DLAMMPS_EXCEPTIONS=definition" under the rug.
+    let needle = CcFlag::Define(String::from("LAMMPS_EXCEPTIONS"));
+    if defines.0.iter().any(|x| x == &needle) {
+        return ExceptionSupport::Defined;
+    }
+
+    match parse_version(version) {
+        Some(date) if date >= ALWAYS_HAS_EXCEPTIONS_SINCE => return ExceptionSupport::Detected,
+        // There's no way to tell without running lammps_config_has_exceptions, and
+        // a library without exceptions would exit() on the first error, so we must
+        // assume the worst.
+        Some(date) if date >= ALWAYS_EXPORTS_ERROR_FNS_SINCE => return ExceptionSupport::Unsupported,
+        _ => {},
+    }
+
+    // Older versions only compile these functions when exceptions are enabled.
+    //
+    // NOTE: If the version is unknown, this can still give a false positive for
+    //       versions between late 2020 and early 2023.
+    let exported = lib_file.and_then(|lib_file| ::audit::exported_symbols(lib_file).ok());
+    if let Some(exported) = exported {
+        let required = ["lammps_has_error", "lammps_get_last_error_message"];
+        if required.iter().all(|&name| exported.contains(name)) {
+            return ExceptionSupport::Detected;
+        }
+    }
+    ExceptionSupport::Unsupported
+}
+
+// Parse a version reported by pkg-config into a date like 20190807, if it looks like one.
+//
+// Versions of the CMake build that report a version at all use this format. (perhaps with dots)
+fn parse_version(version: &str) -> Option<u32> {
+    let digits: String = version.chars().filter(|c| c.is_ascii_digit()).collect();
+    match digits.len() == 8 && digits.starts_with("20") {
+        true => digits.parse().ok(),
+        false => None,
+    }
+}
+
+// ----------------------------------------------------
+
+// pkg-config-rs refuses to run when cross-compiling unless PKG_CONFIG_ALLOW_CROSS is set,
+// because pkg-config would happily report the host's libraries.  If we know the target's
+// sysroot then we can let it through, and make sure that pkg-config knows about it too.
+//
+// (PKG_CONFIG_PATH or PKG_CONFIG_LIBDIR must still be set by the user to point into
+//  the sysroot)
+fn prepare_pkg_config_for_target() {
+    if !::env::is_cross() {
+        return;
+    }
+    if let Some(sysroot) = ::env::sysroot() {
+        // HACK: pkg-config-rs only reads these from our own environment.
+        ::std::env::set_var(format!("PKG_CONFIG_SYSROOT_DIR_{}", ::env::target()), sysroot.as_path());
+        ::std::env::set_var("PKG_CONFIG_ALLOW_CROSS", "1");
+    }
+}
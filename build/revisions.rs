@@ -0,0 +1,201 @@
+// the releases of LAMMPS that we know how to build from source
+
+use ::BoxResult;
+use ::std::process::Command;
+use ::path_abs::{PathDir, PathFile};
+
+/// A release of LAMMPS.
+pub(crate) struct Revision {
+    /// The git tag, e.g. `"stable_7Aug2019"`.
+    pub(crate) tag: &'static str,
+    // The LAMMPS_VERSION string in src/version.h, used to verify archives.
+    version_string: &'static str,
+    // The CMake names of packages that differ from those of the default revision.
+    //
+    // An empty list means that the package no longer exists.
+    renamed_packages: &'static [(&'static str, &'static [&'static str])],
+}
+
+/// All known-good revisions.  The first one is the revision of the `lammps` submodule.
+const REVISIONS: &[Revision] = &[
+    Revision {
+        tag: "stable_7Aug2019",
+        version_string: "7 Aug 2019",
+        renamed_packages: &[],
+    },
+    Revision {
+        tag: "stable_3Mar2020",
+        version_string: "3 Mar 2020",
+        renamed_packages: &[],
+    },
+    Revision {
+        // Most packages were renamed in this release; the USER- prefix was dropped,
+        // and several packages were split up or reorganized.
+        tag: "stable_29Sep2021",
+        version_string: "29 Sep 2021",
+        renamed_packages: &[
+            ("PKG_MEAM", &[]), // the Fortran MEAM was removed, and USER-MEAMC took its name
+            ("PKG_REAX", &[]),
+            ("PKG_SNAP", &["PKG_ML-SNAP"]),
+            ("PKG_USER-ATC", &["PKG_ATC"]),
+            ("PKG_USER-AWPMD", &["PKG_AWPMD"]),
+            ("PKG_USER-BOCS", &["PKG_BOCS"]),
+            ("PKG_USER-CGDNA", &["PKG_CG-DNA"]),
+            ("PKG_USER-CGSDK", &["PKG_CG-SDK"]),
+            ("PKG_USER-COLVARS", &["PKG_COLVARS"]),
+            ("PKG_USER-DIFFRACTION", &["PKG_DIFFRACTION"]),
+            ("PKG_USER-DPD", &["PKG_DPD-REACT"]),
+            ("PKG_USER-DRUDE", &["PKG_DRUDE"]),
+            ("PKG_USER-EFF", &["PKG_EFF"]),
+            ("PKG_USER-FEP", &["PKG_FEP"]),
+            ("PKG_USER-H5MD", &["PKG_H5MD"]),
+            ("PKG_USER-INTEL", &["PKG_INTEL"]),
+            ("PKG_USER-LB", &["PKG_LATBOLTZ"]),
+            ("PKG_USER-MGPT", &["PKG_MGPT"]),
+            ("PKG_USER-MISC", &[
+                "PKG_EXTRA-COMPUTE", "PKG_EXTRA-DUMP", "PKG_EXTRA-FIX",
+                "PKG_EXTRA-MOLECULE", "PKG_EXTRA-PAIR",
+            ]),
+            ("PKG_USER-MANIFOLD", &["PKG_MANIFOLD"]),
+            ("PKG_USER-MEAMC", &["PKG_MEAM"]),
+            ("PKG_USER-MESO", &["PKG_DPD-MESO"]),
+            ("PKG_USER-MOFFF", &["PKG_MOFFF"]),
+            ("PKG_USER-MOLFILE", &["PKG_MOLFILE"]),
+            ("PKG_USER-NETCDF", &["PKG_NETCDF"]),
+            ("PKG_USER-OMP", &["PKG_OPENMP"]),
+            ("PKG_USER-PHONON", &["PKG_PHONON"]),
+            ("PKG_USER-QMMM", &["PKG_QMMM"]),
+            ("PKG_USER-QTB", &["PKG_QTB"]),
+            ("PKG_USER-QUIP", &["PKG_ML-QUIP"]),
+            ("PKG_USER-REAXC", &["PKG_REAXFF"]),
+            ("PKG_USER-SMD", &["PKG_MACHDYN"]),
+            ("PKG_USER-SMTBQ", &["PKG_SMTBQ"]),
+            ("PKG_USER-SPH", &["PKG_SPH"]),
+            ("PKG_USER-TALLY", &["PKG_TALLY"]),
+            ("PKG_USER-UEF", &["PKG_UEF"]),
+            ("PKG_USER-VTK", &["PKG_VTK"]),
+        ],
+    },
+];
+
+/// The revision chosen by `RUST_LAMMPS_VERSION`.
+pub(crate) fn selected() -> &'static Revision {
+    let tag = match ::env::lammps_version() {
+        Some(tag) => tag,
+        None => return &REVISIONS[0],
+    };
+    // be forgiving about the "stable_" prefix
+    let found = REVISIONS.iter().find(|rev| rev.tag == tag || rev.tag == format!("stable_{}", tag));
+    found.unwrap_or_else(|| panic!(
+        "Unknown RUST_LAMMPS_VERSION: {}. Known versions are: {}",
+        tag, REVISIONS.iter().map(|rev| rev.tag).collect::<Vec<_>>().join(", "),
+    ))
+}
+
+/// Guess the revision of a LAMMPS source tree that was configured with CMake.
+///
+/// This is read from `src/version.h` if it is a known release.  Otherwise, the first
+/// revision whose names for the requested packages are all known to the CMake cache is
+/// used, so that at least the package names are right.
+pub(crate) fn of_build_tree(lmp_dir: &PathDir, has_cache_key: &dyn Fn(&str) -> bool) -> &'static Revision {
+    let version_h = ::std::fs::read_to_string(lmp_dir.join("src").join("version.h")).unwrap_or_default();
+    let from_version = REVISIONS.iter().find(|rev| version_h.contains(&format!("\"{}\"", rev.version_string)));
+    if let Some(rev) = from_version {
+        return rev;
+    }
+    let names_match = |rev: &&Revision| match rev.cmake_package_flags() {
+        Ok(flags) => flags.iter().all(|flag| has_cache_key(flag)),
+        Err(_) => false,
+    };
+    REVISIONS.iter().find(names_match).unwrap_or(&REVISIONS[0])
+}
+
+impl Revision {
+    /// Is this the revision checked out in the `lammps` submodule?
+    pub(crate) fn is_submodule(&self) -> bool {
+        self.tag == REVISIONS[0].tag
+    }
+
+    /// The `PKG_*` CMake variables for the packages requested through cargo features.
+    pub(crate) fn cmake_package_flags(&self) -> Result<Vec<&'static str>, String> {
+        let mut out = vec![];
+        let mut removed = vec![];
+        for flag in ::packages::cmake_flags_from_features() {
+            match self.renamed_packages.iter().find(|&&(old, _)| old == flag) {
+                None => out.push(flag),
+                Some(&(_, &[])) => removed.push(flag),
+                Some(&(_, new)) => out.extend(new.iter().cloned()),
+            }
+        }
+        match removed.is_empty() {
+            true => Ok(out),
+            false => Err(format!("LAMMPS {} does not have these packages: {}", self.tag, removed.join(", "))),
+        }
+    }
+
+    /// Extract the source archive for this revision into `OUT_DIR`.
+    ///
+    /// The archive is not downloaded; it must be in `RUST_LAMMPS_ARCHIVE_DIR`.
+    pub(crate) fn extract_archive(&self) -> BoxResult<PathDir> {
+        let archive = self.archive_file()?;
+        ::rerun_if_changed(archive.display());
+
+        let dest = ::env::out_dir().join(format!("lammps-archive-{}", self.tag));
+        let done_marker = dest.join(".extracted");
+        if !done_marker.exists() {
+            if dest.exists() {
+                ::std::fs::remove_dir_all(&dest)?;
+            }
+            ::std::fs::create_dir(&dest)?;
+
+            let status = {
+                Command::new("tar")
+                    .arg("-xzf").arg(archive.as_path())
+                    .arg("-C").arg(dest.as_path())
+                    .status()
+                    .map_err(|e| format!("could not run tar: {}", e))?
+            };
+            if !status.success() {
+                return Err(format!("failed to extract {}", archive.display()).into());
+            }
+            ::std::fs::write(&done_marker, "")?;
+        }
+
+        // The archive contains a single directory like 'lammps-stable_7Aug2019'.
+        let root = {
+            ::std::fs::read_dir(&dest)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .find(|path| path.join("src").join("version.h").exists())
+                .ok_or_else(|| format!("{} does not look like a LAMMPS source archive", archive.display()))?
+        };
+
+        let version_h = ::std::fs::read_to_string(root.join("src").join("version.h"))?;
+        if !version_h.contains(&format!("\"{}\"", self.version_string)) {
+            return Err(format!(
+                "{} is not LAMMPS {}.  (src/version.h: {})",
+                archive.display(), self.tag, version_h.trim(),
+            ).into());
+        }
+
+        // crate-packaged trees lack this; archives should have it, but it's cheap to be sure.
+        PathDir::create(root.join("potentials"))?;
+        Ok(PathDir::new(root)?)
+    }
+
+    fn archive_file(&self) -> BoxResult<PathFile> {
+        let dir = ::env::archive_dir().ok_or_else(|| format!(
+            "RUST_LAMMPS_VERSION={} requires a source archive.  Download \
+             https://github.com/lammps/lammps/archive/{}.tar.gz and set \
+             RUST_LAMMPS_ARCHIVE_DIR to the directory containing it.",
+            self.tag, self.tag,
+        ))?;
+
+        // GitHub offers the same archive under both names.
+        let names = [format!("lammps-{}.tar.gz", self.tag), format!("{}.tar.gz", self.tag)];
+        names.iter()
+            .filter_map(|name| PathFile::new(dir.join(name)).ok())
+            .next()
+            .ok_or_else(|| format!("could not find {} in {}", names.join(" or "), dir.display()).into())
+    }
+}
@@ -0,0 +1,237 @@
+// checks for combinations of compiler and LAMMPS features that are known to be broken
+
+use ::std::fmt;
+use ::std::process::Command;
+
+/// A family of C++ compilers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum CompilerKind {
+    Gcc,
+    Clang,
+    AppleClang,
+    Intel,
+}
+
+impl fmt::Display for CompilerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompilerKind::Gcc => write!(f, "GCC"),
+            CompilerKind::Clang => write!(f, "Clang"),
+            CompilerKind::AppleClang => write!(f, "Apple Clang"),
+            CompilerKind::Intel => write!(f, "Intel"),
+        }
+    }
+}
+
+/// A C++ compiler that has been identified from its `--version` output.
+pub(crate) struct Compiler {
+    pub(crate) command: String,
+    pub(crate) kind: CompilerKind,
+    pub(crate) version: (u32, u32),
+}
+
+impl fmt::Display for Compiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}.{} ({})", self.kind, self.version.0, self.version.1, self.command)
+    }
+}
+
+// ----------------------------------------------------
+
+/// A problem with the LAMMPS built by this crate that only occurs on certain compilers.
+struct KnownIssue {
+    kind: CompilerKind,
+    // first affected version
+    since: (u32, u32),
+    // tags of the affected LAMMPS revisions
+    revisions: &'static [&'static str],
+    // only matters if this returns true (e.g. if some cargo feature is enabled)
+    applies: fn() -> bool,
+    description: &'static str,
+}
+
+const KNOWN_ISSUES: &[KnownIssue] = &[
+    KnownIssue {
+        kind: CompilerKind::Gcc,
+        since: (9, 0),
+        // fixed upstream by 3Mar2020
+        revisions: &["stable_7Aug2019"],
+        applies: uses_openmp,
+        description: "\
+            the OpenMP pragmas in this version of LAMMPS do not compile under GCC 9, so its \
+            CMake files will silently build USER-OMP without OpenMP. \
+            (see https://github.com/lammps/lammps/issues/1482)\
+        ",
+    },
+];
+
+fn uses_openmp() -> bool { cfg!(feature = "package-user-omp") }
+
+// Alternatives to try under RUST_LAMMPS_COMPILER_CHECK=fallback, as (CC, CXX).
+const FALLBACK_COMPILERS: &[(&str, &str)] = &[
+    ("clang", "clang++"),
+];
+
+/// What to do when the compiler has a known issue.  (`RUST_LAMMPS_COMPILER_CHECK`)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum CheckMode {
+    Warn,
+    Error,
+    Fallback,
+}
+
+// ----------------------------------------------------
+
+/// Check the C++ compiler that CMake will use against the list of known issues.
+///
+/// Under `RUST_LAMMPS_COMPILER_CHECK=fallback`, this may switch CMake to a different
+/// compiler.  If the compiler can't be identified, the check is skipped.
+pub(crate) fn check_cmake_compiler(cmake: &mut ::cmake::Config) {
+    if ::env::cmake_toolchain_file().is_some() {
+        return; // no telling what it picks
+    }
+    let command = match ::env::target_compiler("CXX") {
+        Some(cxx) => cxx,
+        None => match default_cxx() {
+            Some(cxx) => cxx.to_string(),
+            None => return,
+        },
+    };
+
+    let compiler = match identify(&command) {
+        Ok(compiler) => compiler,
+        Err(e) => {
+            println!("cargo:warning=skipping compiler check: {}", e);
+            return;
+        },
+    };
+
+    let issues = known_issues(&compiler);
+    if issues.is_empty() {
+        return;
+    }
+    let explanation = {
+        issues.iter()
+            .map(|issue| format!("{} has a known issue: {}", compiler, issue.description))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    match ::env::compiler_check() {
+        CheckMode::Warn => {
+            for line in explanation.lines() {
+                println!("cargo:warning={}", line);
+            }
+            println!("cargo:warning=set RUST_LAMMPS_COMPILER_CHECK=fallback to try another compiler.");
+        },
+        CheckMode::Error => {
+            panic!("{}\n(set RUST_LAMMPS_COMPILER_CHECK=fallback to try another compiler)", explanation);
+        },
+        CheckMode::Fallback => match find_fallback() {
+            Some((cc, cxx)) => {
+                for line in explanation.lines() {
+                    println!("cargo:warning={}", line);
+                }
+                println!("cargo:warning=building LAMMPS with {} instead.", cxx);
+                cmake.define("CMAKE_C_COMPILER", cc);
+                cmake.define("CMAKE_CXX_COMPILER", &cxx.command);
+            },
+            None => panic!("{}\nNo working alternative was found among: {}", explanation, {
+                FALLBACK_COMPILERS.iter().map(|&(_, cxx)| cxx).collect::<Vec<_>>().join(", ")
+            }),
+        },
+    }
+}
+
+// The C++ compiler that cmake-rs (by way of the cc crate) will pick when CXX isn't set.
+fn default_cxx() -> Option<&'static str> {
+    let target = ::env::target();
+    match () {
+        _ if ::env::is_cross() => None, // the cc crate has rules for these that we won't duplicate
+        _ if target.contains("-apple-") || target.contains("-freebsd") => Some("clang++"),
+        _ if target.contains("-windows-msvc") => None,
+        _ => Some("c++"),
+    }
+}
+
+fn known_issues(compiler: &Compiler) -> Vec<&'static KnownIssue> {
+    KNOWN_ISSUES.iter()
+        .filter(|issue| issue.kind == compiler.kind)
+        .filter(|issue| compiler.version >= issue.since)
+        .filter(|issue| issue.revisions.contains(&::revisions::selected().tag))
+        .filter(|issue| (issue.applies)())
+        .collect()
+}
+
+// Find an alternative compiler that works and has no known issues.
+fn find_fallback() -> Option<(&'static str, Compiler)> {
+    FALLBACK_COMPILERS.iter().filter_map(|&(cc, cxx)| {
+        let compiler = identify(cxx).ok()?;
+        if !known_issues(&compiler).is_empty() {
+            return None;
+        }
+        // e.g. clang is useless for USER-OMP if libomp is not installed
+        if uses_openmp() && !supports_openmp(cxx) {
+            println!("cargo:warning=not using {} because it cannot compile OpenMP programs (is libomp installed?)", cxx);
+            return None;
+        }
+        Some((cc, compiler))
+    }).next()
+}
+
+/// Identify a compiler by running `<command> --version`.
+pub(crate) fn identify(command: &str) -> Result<Compiler, String> {
+    let output = {
+        Command::new(command).arg("--version").output()
+            .map_err(|e| format!("could not run `{} --version`: {}", command, e))?
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first_line = stdout.lines().next().unwrap_or("");
+
+    // The first line looks like one of:
+    //     g++ (Ubuntu 9.3.0-17ubuntu1~20.04) 9.3.0
+    //     c++ (GCC) 9.2.0
+    //     clang version 10.0.0-4ubuntu1
+    //     Apple clang version 11.0.0 (clang-1100.0.33.8)
+    //     icpc (ICC) 19.0.1.144 20181018
+    let kind = match () {
+        _ if first_line.starts_with("Apple") => CompilerKind::AppleClang,
+        _ if first_line.contains("clang") => CompilerKind::Clang,
+        _ if first_line.contains("ICC") || first_line.contains("icpc") => CompilerKind::Intel,
+        _ if first_line.contains("GCC") || first_line.contains("g++") || stdout.contains("Free Software Foundation") => CompilerKind::Gcc,
+        _ => return Err(format!("could not identify the compiler from `{} --version`: {}", command, first_line)),
+    };
+    let version = {
+        first_line.split(|c: char| c.is_whitespace() || c == '(')
+            .filter_map(parse_version)
+            .next()
+            .ok_or_else(|| format!("could not find a version number in `{} --version`: {}", command, first_line))?
+    };
+    Ok(Compiler { command: command.to_string(), kind, version })
+}
+
+// Parse the "9.3" out of "9.3.0-17ubuntu1~20.04)".
+fn parse_version(word: &str) -> Option<(u32, u32)> {
+    let mut parts = word.splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?;
+    let minor_len = minor.find(|c: char| !c.is_ascii_digit()).unwrap_or(minor.len());
+    let minor = minor[..minor_len].parse().ok()?;
+    Some((major, minor))
+}
+
+// Try to build a tiny OpenMP program.
+fn supports_openmp(cxx: &str) -> bool {
+    let dir = ::env::out_dir();
+    let src = dir.join("openmp-test.cpp");
+    let exe = dir.join("openmp-test");
+    let code = "#include <omp.h>\nint main() { return omp_get_max_threads() > 0 ? 0 : 1; }\n";
+    if ::std::fs::write(&src, code).is_err() {
+        return false;
+    }
+    let status = Command::new(cxx).arg("-fopenmp").arg(src.as_path()).arg("-o").arg(exe.as_path()).output();
+    match status {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,146 @@
+// linking against an uninstalled LAMMPS CMake build directory
+
+use ::{BoxResult, PanicResult};
+use ::{BuildMeta, CcFlag, CcFlags};
+use ::std::collections::HashMap;
+use ::std::io::BufReader;
+use ::std::io::prelude::*;
+use ::path_abs::{PathArc, PathDir, PathFile, FileRead};
+
+/// Use the library and headers from a configured-and-built LAMMPS CMake build tree.
+///
+/// Everything we need to know is recovered from its `CMakeCache.txt`.
+pub(crate) fn link_build_tree(build_dir: &PathDir) -> PanicResult<BuildMeta> {
+    let cache_path = build_dir.join("CMakeCache.txt");
+    let cache = read_cmake_cache(&cache_path).map_err(|e| {
+        format!("RUST_LAMMPS_BUILD_DIR: could not read {}: {}", cache_path.display(), e)
+    })?;
+    ::rerun_if_changed(cache_path.display());
+
+    // This is the 'cmake' directory; the repository root is one level up.
+    let cmake_dir = PathDir::new(cache_value(&cache, "CMAKE_HOME_DIRECTORY")?)?;
+    let lmp_dir = PathDir::new(cmake_dir.parent().expect("cmake dir has no parent"))?;
+
+    let revision = ::revisions::of_build_tree(&lmp_dir, &|key| cache.contains_key(key));
+    check_cache_against_features(&cache, revision)?;
+
+    let mut defines = CcFlags(vec![]);
+    if let Some(sizes) = cache.get("LAMMPS_SIZES") {
+        defines.0.push(CcFlag::Define(format!("LAMMPS_{}", sizes.to_uppercase())));
+    }
+    if cache_bool(&cache, "LAMMPS_EXCEPTIONS") {
+        defines.0.push(CcFlag::Define("LAMMPS_EXCEPTIONS".into()));
+    }
+
+    // LAMMPS' CMakeLists names the library e.g. liblammps_mpi.so if LAMMPS_MACHINE=mpi
+    let (lib_name, exe_name) = match cache.get("LAMMPS_MACHINE").map(|s| &s[..]) {
+        None | Some("") => (String::from("lammps"), String::from("lmp")),
+        Some(machine) => (format!("lammps_{}", machine), format!("lmp_{}", machine)),
+    };
+    let lib_file = {
+        ["so", "dylib", "a"].iter()
+            .filter_map(|ext| PathFile::new(build_dir.join(format!("lib{}.{}", lib_name, ext))).ok())
+            .next()
+            .ok_or_else(|| format!("RUST_LAMMPS_BUILD_DIR: lib{} not found in {}; was it built?", lib_name, build_dir.display()))?
+    };
+    ::rerun_if_changed(lib_file.display());
+
+    let exe_file = match cfg!(feature = "exe") {
+        true => Some(PathFile::new(build_dir.join(&exe_name)).map_err(|_| {
+            format!("RUST_LAMMPS_BUILD_DIR: the `exe` feature requires {} in {}; configure with -DBUILD_EXE=yes", exe_name, build_dir.display())
+        })?),
+        false => None,
+    };
+
+    let mut link_flags = CcFlags(vec![CcFlag::LibDir(build_dir.clone().into())]);
+    if lib_file.extension() == Some("a".as_ref()) {
+        link_flags.0.push(CcFlag::StaticLib(lib_name));
+        link_flags.0.push(CcFlag::Lib(match ::env::target().contains("-apple-") {
+            true => "c++".into(),
+            false => "stdc++".into(),
+        }));
+    } else {
+        link_flags.0.push(CcFlag::Lib(lib_name));
+        // Help our own examples find it at runtime.
+        link_flags.0.push(CcFlag::LinkArg(format!("-Wl,-rpath,{}", build_dir.display())));
+    }
+
+    Ok(BuildMeta {
+        source: "build-dir",
+        header: "src/library.h",
+        include_dirs: CcFlags(vec![CcFlag::IncludeDir(lmp_dir.into())]),
+        defines,
+        compile_flags: CcFlags(vec![]),
+        link_flags,
+        lib_file: Some(lib_file),
+        exe_file,
+    })
+}
+
+// The tree is already built, so all we can do is complain if it doesn't match the features.
+fn check_cache_against_features(cache: &HashMap<String, String>, revision: &::revisions::Revision) -> Result<(), String> {
+    let mut problems = vec![];
+    match revision.cmake_package_flags() {
+        Ok(keys) => for key in keys {
+            if !cache_bool(cache, key) {
+                problems.push(format!("{} is not enabled (needed by a package-* feature)", key));
+            }
+        },
+        Err(e) => problems.push(e),
+    }
+    if cfg!(feature = "exceptions") && !cache_bool(cache, "LAMMPS_EXCEPTIONS") {
+        problems.push(String::from("LAMMPS_EXCEPTIONS is not enabled (needed by feature 'exceptions')"));
+    }
+    if cfg!(feature = "mpi") && !cache_bool(cache, "BUILD_MPI") {
+        problems.push(String::from("BUILD_MPI is not enabled (needed by feature 'mpi')"));
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(format!(
+            "RUST_LAMMPS_BUILD_DIR does not match the enabled cargo features:\n    {}\n\
+            Reconfigure it with cmake and rebuild.",
+            problems.join("\n    "),
+        )),
+    }
+}
+
+// ----------------------------------------------------
+
+// Entries look like "PKG_MANYBODY:BOOL=ON".  Comments begin with "#" or "//".
+fn read_cmake_cache(path: &PathArc) -> BoxResult<HashMap<String, String>> {
+    let file = BufReader::new(FileRead::read(path)?);
+    let mut out = HashMap::new();
+    for line in file.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            continue;
+        }
+        let (key, value) = match line.find('=') {
+            Some(i) => (&line[..i], &line[i + 1..]),
+            None => continue,
+        };
+        let key = key.split(':').next().unwrap();
+        out.insert(key.to_string(), value.to_string());
+    }
+    Ok(out)
+}
+
+fn cache_value<'a>(cache: &'a HashMap<String, String>, key: &str) -> Result<&'a str, String> {
+    match cache.get(key) {
+        Some(value) => Ok(value),
+        None => Err(format!("RUST_LAMMPS_BUILD_DIR: {} not found in CMakeCache.txt", key)),
+    }
+}
+
+// CMake's notion of truthiness. (minus the "<something>-NOTFOUND" special case)
+fn cache_bool(cache: &HashMap<String, String>, key: &str) -> bool {
+    match cache.get(key).map(|s| s.to_uppercase()) {
+        Some(value) => match &value[..] {
+            "ON" | "YES" | "TRUE" | "Y" => true,
+            s => s.parse::<i64>().map(|x| x != 0).unwrap_or(false),
+        },
+        None => false,
+    }
+}
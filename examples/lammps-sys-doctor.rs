@@ -0,0 +1,562 @@
+// lammps-sys-doctor - Diagnoses problems with linking and running LAMMPS.
+
+// Usage:
+//
+//     cargo run --example=lammps-sys-doctor  [other cargo arguments...]
+//
+// Be sure to run this with the same environment variables and `--features`
+// that you plan to use in your own project.  (e.g. `--features=mpi,package-user-omp`)
+// Under the `mpi` feature, you can also run the compiled binary under `mpirun`.
+//
+// The doctor reports how LAMMPS was obtained, and then runs a series of checks.
+// Each failed check prints some advice, and the exit status is nonzero if any failed.
+//
+// The vast majority of possible problems will manifest during the linking of
+// this binary, before it is run.  If cargo reports failure running a "cc"
+// command, please see the README and doc/linking-a-system-library.md.
+//
+// Some problems (notably, mixing implementations of MPI) may cause a segfault
+// rather than a failed check.  The doctor prints what it is about to do before
+// each step, so the last line of output will tell you where it died.
+
+extern crate lammps_sys;
+#[cfg(feature = "mpi")]
+extern crate mpi_sys;
+
+use ::std::ffi::{CStr, CString};
+use ::std::io::{BufRead, BufReader, Write};
+use ::std::os::raw::{c_char, c_int};
+use ::std::path::{Path, PathBuf};
+use ::lammps_sys::{lammps_instance, BUILD_INFO};
+
+// Number of threads requested from `package omp`.
+const OMP_TEST_THREADS: usize = 2;
+
+fn main() {
+    let mut doctor = Doctor::default();
+
+    #[cfg(feature = "mpi")]
+    let (rank, size) = unsafe { init_mpi() };
+    #[cfg(not(feature = "mpi"))]
+    let (rank, size) = (0, 1);
+    // only the root prints the report
+    doctor.quiet = rank != 0;
+
+    doctor.section("How LAMMPS was obtained");
+    doctor.info(&format!("source: {}", match BUILD_INFO.source {
+        "system" => "system library (found by pkg-config)",
+        "build" => "built from source",
+        "build-dir" => "CMake build directory (RUST_LAMMPS_BUILD_DIR)",
+        "none" => "none (RUST_LAMMPS_SOURCE=none)",
+        other => other,
+    }));
+    doctor.info(&format!("linked library: {}", BUILD_INFO.library.unwrap_or("(unknown)")));
+    if let Some(exe) = BUILD_INFO.exe {
+        doctor.info(&format!("lmp executable: {}", exe));
+    }
+    doctor.info(&format!("requested packages: {}", match BUILD_INFO.packages {
+        [] => String::from("(none)"),
+        packages => packages.join(" "),
+    }));
+
+    doctor.section("Library resolution");
+    check_loaded_library(&mut doctor);
+    check_library_path(&mut doctor);
+
+    doctor.section("LAMMPS instance");
+    doctor.step("creating an instance");
+    let lmp = unsafe { open_instance() };
+    if lmp.is_null() {
+        doctor.fail("lammps_open returned a null pointer.", "\
+            LAMMPS failed to initialize.  If you are using MPI, make sure that liblammps was built \
+            against the same MPI implementation as the one used by `mpicc`.\
+        ");
+        doctor.finish();
+    }
+    doctor.ok("created an instance");
+    unsafe {
+        doctor.info(&format!("version: {}", ::lammps_sys::lammps_version(lmp)));
+    }
+
+    let installed = installed_packages();
+    doctor.info(&format!("installed packages: {}", match installed.len() {
+        0 => String::from("(none)"),
+        _ => installed.join(" "),
+    }));
+    doctor.info(&format!("compression and image support: {}", describe_io_support()));
+    check_packages(&mut doctor, &installed);
+    check_exceptions(&mut doctor);
+
+    doctor.section("OpenMP");
+    check_openmp(&mut doctor, lmp, &installed, rank);
+
+    #[cfg(feature = "mpi")]
+    {
+        doctor.section("MPI");
+        check_mpi(&mut doctor, rank, size);
+    }
+    let _ = size;
+
+    doctor.step("closing the instance");
+    unsafe { ::lammps_sys::lammps_close(lmp); }
+    doctor.ok("closed the instance");
+
+    #[cfg(feature = "mpi")]
+    unsafe { ::mpi_sys::MPI_Finalize(); }
+
+    doctor.finish();
+}
+
+// ----------------------------------------------------
+
+#[derive(Default)]
+struct Doctor {
+    failures: usize,
+    quiet: bool,
+}
+
+impl Doctor {
+    fn section(&self, title: &str) {
+        if !self.quiet {
+            println!();
+            println!("== {} ==", title);
+        }
+    }
+
+    fn info(&self, msg: &str) {
+        if !self.quiet {
+            println!("       {}", msg);
+        }
+    }
+
+    // Announce something that might crash.
+    fn step(&self, msg: &str) {
+        if !self.quiet {
+            print!("  ...  {}\r", msg);
+            let _ = ::std::io::stdout().flush();
+        }
+    }
+
+    fn ok(&self, msg: &str) {
+        if !self.quiet {
+            println!("[ ok ] {}", msg);
+        }
+    }
+
+    fn skip(&self, msg: &str) {
+        if !self.quiet {
+            println!("[skip] {}", msg);
+        }
+    }
+
+    fn fail(&mut self, msg: &str, advice: &str) {
+        self.failures += 1;
+        if !self.quiet {
+            println!("[FAIL] {}", msg);
+            for line in wrap(advice, 72) {
+                println!("         {}", line);
+            }
+        }
+    }
+
+    fn finish(&self) -> ! {
+        if !self.quiet {
+            println!();
+            match self.failures {
+                0 => println!("All checks passed."),
+                n => println!("{} check(s) failed.", n),
+            }
+        }
+        ::std::process::exit(match self.failures { 0 => 0, _ => 1 });
+    }
+}
+
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(line.clone());
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+// ----------------------------------------------------
+// Library resolution
+
+fn library_path_var() -> &'static str {
+    match cfg!(target_os = "macos") {
+        true => "DYLD_LIBRARY_PATH",
+        false => "LD_LIBRARY_PATH",
+    }
+}
+
+// The linked library, if it is a shared library.
+fn linked_shared_library() -> Option<PathBuf> {
+    let path = Path::new(BUILD_INFO.library?);
+    match path.extension().and_then(|s| s.to_str()) {
+        Some("a") => None,
+        _ => Some(path.to_path_buf()),
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// Find out which liblammps the dynamic loader actually gave us.
+fn check_loaded_library(doctor: &mut Doctor) {
+    let linked = match (BUILD_INFO.library, linked_shared_library()) {
+        (None, _) => {
+            doctor.skip("the build script did not record which library was linked");
+            return;
+        },
+        (Some(_), None) => {
+            doctor.ok("liblammps is linked statically");
+            return;
+        },
+        (Some(_), Some(linked)) => linked,
+    };
+
+    let loaded = match loaded_libraries() {
+        Some(loaded) => loaded,
+        None => {
+            doctor.skip("can't list loaded libraries on this platform (needs /proc/self/maps)");
+            return;
+        },
+    };
+    let loaded_lammps: Vec<_> = {
+        loaded.into_iter()
+            .filter(|path| {
+                let name = path.file_name().and_then(|s| s.to_str()).unwrap_or("");
+                name.starts_with("liblammps")
+            })
+            .collect()
+    };
+
+    match &loaded_lammps[..] {
+        [] => doctor.fail("no liblammps appears to be loaded in this process.", &format!("\
+            Expected to find {}.  This is unusual; please file a bug report.\
+        ", linked.display())),
+        [loaded] if same_file(loaded, &linked) => {
+            doctor.ok(&format!("loaded {}", loaded.display()));
+        },
+        loaded => {
+            let loaded: Vec<_> = loaded.iter().map(|p| p.display().to_string()).collect();
+            doctor.fail(&format!("loaded {} at runtime", loaded.join(", ")), &format!("\
+                The dynamic loader found a different liblammps from the one this program \
+                was linked against ({}).  This can lead to missing symbols or crashes.  Make \
+                sure that its directory comes first in {}, or remove the other library.\
+            ", linked.display(), library_path_var()));
+        },
+    }
+}
+
+// Paths of shared objects mapped into this process.
+fn loaded_libraries() -> Option<Vec<PathBuf>> {
+    let file = ::std::fs::File::open("/proc/self/maps").ok()?;
+    let mut out: Vec<PathBuf> = vec![];
+    for line in BufReader::new(file).lines() {
+        let line = line.ok()?;
+        // lines look like "7f0c1a2b3000-7f0c1a2b4000 r-xp 00000000 08:01 1234 /usr/lib/liblammps.so.0"
+        let path = match line.splitn(6, ' ').nth(5).map(str::trim) {
+            Some(path) if path.starts_with('/') => PathBuf::from(path),
+            _ => continue,
+        };
+        if !out.contains(&path) {
+            out.push(path);
+        }
+    }
+    Some(out)
+}
+
+// Outside of `cargo run`, the library will be looked up through LD_LIBRARY_PATH.
+// (well, also rpaths and the system directories, but this is what users tend to get wrong)
+fn check_library_path(doctor: &mut Doctor) {
+    let var = library_path_var();
+    let linked = match linked_shared_library() {
+        Some(linked) => linked,
+        None => {
+            doctor.skip(&format!("{} is irrelevant without a shared library", var));
+            return;
+        },
+    };
+    let file_name = linked.file_name().expect("library has no file name").to_owned();
+
+    let search_path = ::std::env::var_os(var).unwrap_or_default();
+    let found = {
+        ::std::env::split_paths(&search_path)
+            .map(|dir| dir.join(&file_name))
+            .find(|path| path.exists())
+    };
+    let linked_parent = linked.parent().expect("library has no parent");
+    let linked_dir = linked_parent.display().to_string();
+
+    match found {
+        Some(ref found) if same_file(found, &linked) => {
+            doctor.ok(&format!("{} resolves to {}", var, found.display()));
+        },
+        Some(found) => doctor.fail(&format!("{} resolves to {}", var, found.display()), &format!("\
+            This is not the library that was linked.  Programs run outside of cargo may load \
+            the wrong LAMMPS.  Put {} before {} in {}.\
+        ", linked_dir, found.parent().unwrap().display(), var)),
+        None => match BUILD_INFO.source {
+            // These live in OUT_DIR or were given an rpath, so they work anyways under cargo.
+            "build" | "build-dir" => {
+                doctor.skip(&format!("{} does not contain {} (fine under `cargo run`)", var, linked_dir));
+            },
+            _ if is_default_library_dir(linked_parent) => {
+                doctor.ok(&format!("{} is a default library directory", linked_dir));
+            },
+            _ if was_loaded(&linked) => {
+                doctor.skip(&format!("{} does not contain {}, but the loader found it anyway", var, linked_dir));
+            },
+            _ => doctor.fail(&format!("{} does not contain {}", var, linked_dir), &format!("\
+                Unless this directory is a system library directory, programs will fail to \
+                start with an error about a missing shared library.  Add it to {}.\
+            ", var)),
+        },
+    }
+}
+
+// Directories searched by the dynamic loader even when they are not in LD_LIBRARY_PATH.
+//
+// (this doesn't read /etc/ld.so.conf, but `was_loaded` covers for that)
+fn is_default_library_dir(dir: &Path) -> bool {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let defaults = ["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/usr/local/lib"];
+    if defaults.iter().any(|&default| dir == Path::new(default)) {
+        return true;
+    }
+    // multiarch directories like /usr/lib/x86_64-linux-gnu
+    match (dir.parent(), dir.file_name().and_then(|s| s.to_str())) {
+        (Some(parent), Some(name)) => {
+            (parent == Path::new("/lib") || parent == Path::new("/usr/lib")) && name.contains("-linux-")
+        },
+        _ => false,
+    }
+}
+
+// Did the dynamic loader load this exact library into this process?
+fn was_loaded(library: &Path) -> bool {
+    match loaded_libraries() {
+        Some(loaded) => loaded.iter().any(|path| same_file(path, library)),
+        None => false,
+    }
+}
+
+// ----------------------------------------------------
+// Instance and configuration
+
+#[cfg(not(feature = "mpi"))]
+unsafe fn open_instance() -> *mut lammps_instance {
+    let (_args, mut argv) = c_args(&["lammps-sys-doctor", "-log", "none", "-screen", "none"]);
+    let mut lmp: *mut lammps_instance = ::std::ptr::null_mut();
+    ::lammps_sys::lammps_open_no_mpi(argv.len() as c_int, argv.as_mut_ptr(), &mut lmp);
+    lmp
+}
+
+#[cfg(feature = "mpi")]
+unsafe fn open_instance() -> *mut lammps_instance {
+    let (_args, mut argv) = c_args(&["lammps-sys-doctor", "-log", "none", "-screen", "none"]);
+    let mut lmp: *mut lammps_instance = ::std::ptr::null_mut();
+    ::lammps_sys::lammps_open(argv.len() as c_int, argv.as_mut_ptr(), ::mpi_sys::RSMPI_COMM_WORLD, &mut lmp);
+    lmp
+}
+
+// The CStrings must outlive the pointers.
+fn c_args(args: &[&str]) -> (Vec<CString>, Vec<*mut c_char>) {
+    let args: Vec<_> = args.iter().map(|&s| CString::new(s).unwrap()).collect();
+    let argv = args.iter().map(|s| s.as_ptr() as *mut c_char).collect();
+    (args, argv)
+}
+
+unsafe fn command(lmp: *mut lammps_instance, cmd: &str) -> Result<(), String> {
+    let cmd = CString::new(cmd).unwrap();
+    ::lammps_sys::lammps_command(lmp, cmd.as_ptr() as *mut c_char);
+    last_error(lmp)
+}
+
+#[cfg(feature = "exceptions")]
+unsafe fn last_error(lmp: *mut lammps_instance) -> Result<(), String> {
+    if ::lammps_sys::lammps_has_error(lmp) == 0 {
+        return Ok(());
+    }
+    let mut buf = vec![0 as c_char; 1024];
+    ::lammps_sys::lammps_get_last_error_message(lmp, buf.as_mut_ptr(), buf.len() as c_int);
+    Err(CStr::from_ptr(buf.as_ptr()).to_string_lossy().trim().to_string())
+}
+
+// Without exceptions, LAMMPS exits the process on error.
+#[cfg(not(feature = "exceptions"))]
+unsafe fn last_error(_: *mut lammps_instance) -> Result<(), String> { Ok(()) }
+
+fn installed_packages() -> Vec<String> {
+    let mut out = vec![];
+    unsafe {
+        for i in 0..::lammps_sys::lammps_config_package_count() {
+            let mut buf = vec![0 as c_char; 256];
+            if ::lammps_sys::lammps_config_package_name(i, buf.as_mut_ptr(), buf.len() as c_int) != 0 {
+                out.push(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned());
+            }
+        }
+    }
+    out
+}
+
+fn describe_io_support() -> String {
+    let support = unsafe {[
+        ("gzip", ::lammps_sys::lammps_config_has_gzip_support()),
+        ("png", ::lammps_sys::lammps_config_has_png_support()),
+        ("jpeg", ::lammps_sys::lammps_config_has_jpeg_support()),
+        ("ffmpeg", ::lammps_sys::lammps_config_has_ffmpeg_support()),
+    ]};
+    let names: Vec<_> = support.iter().filter(|&&(_, has)| has != 0).map(|&(name, _)| name).collect();
+    match names.len() {
+        0 => String::from("(none)"),
+        _ => names.join(" "),
+    }
+}
+
+fn check_packages(doctor: &mut Doctor, installed: &[String]) {
+    let missing: Vec<_> = {
+        BUILD_INFO.packages.iter()
+            .filter(|&&name| !installed.iter().any(|s| s == name))
+            .cloned()
+            .collect()
+    };
+    match missing.len() {
+        0 => doctor.ok("all requested packages are installed"),
+        _ => doctor.fail(&format!("missing requested packages: {}", missing.join(" ")), &format!("\
+            The {} library was built without packages that were requested through cargo \
+            features.  Rebuild it with {}, or set RUST_LAMMPS_SOURCE=build to have lammps-sys \
+            build LAMMPS itself.\
+        ", BUILD_INFO.source, {
+            missing.iter().map(|name| format!("-DPKG_{}=yes", name)).collect::<Vec<_>>().join(" ")
+        })),
+    }
+}
+
+fn check_exceptions(doctor: &mut Doctor) {
+    let has_exceptions = unsafe { ::lammps_sys::lammps_config_has_exceptions() != 0 };
+    match (cfg!(feature = "exceptions"), has_exceptions) {
+        (true, false) => doctor.fail("the `exceptions` feature is enabled, but LAMMPS has no exception support.", "\
+            Errors will terminate the process instead of being reported.  Rebuild LAMMPS \
+            with -DLAMMPS_EXCEPTIONS=yes.\
+        "),
+        (true, true) => doctor.ok("LAMMPS was built with exception support"),
+        (false, _) => doctor.skip("exception support was not requested"),
+    }
+}
+
+// ----------------------------------------------------
+// OpenMP
+
+fn check_openmp(doctor: &mut Doctor, lmp: *mut lammps_instance, installed: &[String], rank: c_int) {
+    // (renamed in 29Sep2021)
+    let package = match installed.iter().find(|s| *s == "USER-OMP" || *s == "OPENMP") {
+        Some(package) => package,
+        None => {
+            doctor.skip("the USER-OMP (or OPENMP) package is not installed");
+            return;
+        },
+    };
+
+    // LAMMPS has no library function to tell us how many threads it is using,
+    // and if it was compiled without OpenMP support, `package omp` will only
+    // print a warning and continue with one thread.  So we look for the line
+    // that reports the number of threads in a temporary log file.
+    let log_path = ::std::env::temp_dir().join(format!("lammps-sys-doctor-{}.log", ::std::process::id()));
+
+    doctor.step("creating OpenMP threads");
+    let result = unsafe {
+        command(lmp, &format!("log \"{}\"", log_path.display()))
+            .and_then(|()| command(lmp, &format!("package omp {}", OMP_TEST_THREADS)))
+            // closes the log file
+            .and_then(|()| command(lmp, "log none"))
+    };
+    if let Err(e) = result {
+        doctor.fail(&format!("`package omp` failed: {}", e), &format!("\
+            Please see the README for the setup required by the {} package.\
+        ", package));
+        let _ = ::std::fs::remove_file(&log_path);
+        return;
+    }
+
+    // Only the root process writes the log file.
+    if rank != 0 {
+        return;
+    }
+
+    let threads = {
+        ::std::fs::read_to_string(&log_path).ok()
+            .and_then(|text| {
+                text.lines().rev()
+                    .find_map(|line| {
+                        // "  using 2 OpenMP thread(s) per MPI task"
+                        let line = line.trim();
+                        if !line.starts_with("using ") || !line.contains("OpenMP thread") {
+                            return None;
+                        }
+                        line.split_whitespace().nth(1)?.parse::<usize>().ok()
+                    })
+            })
+    };
+    let _ = ::std::fs::remove_file(&log_path);
+
+    match threads {
+        Some(OMP_TEST_THREADS) => doctor.ok(&format!("created {} OpenMP threads", OMP_TEST_THREADS)),
+        Some(n) => doctor.fail(&format!("asked for {} OpenMP threads, but got {}", OMP_TEST_THREADS, n), &format!("\
+            LAMMPS was most likely compiled without -fopenmp, so {} styles will run \
+            on a single thread.  Please see the README for the setup required by the \
+            {} package.\
+        ", package, package)),
+        None => doctor.fail("could not determine the number of OpenMP threads", &format!("\
+            The log file {} did not report a thread count.  Please file a bug report.\
+        ", log_path.display())),
+    }
+}
+
+// ----------------------------------------------------
+// MPI
+
+#[cfg(feature = "mpi")]
+unsafe fn init_mpi() -> (c_int, c_int) {
+    // (printed by every rank, since we don't know our rank yet)
+    print!("  ...  initializing MPI\r");
+    let _ = ::std::io::stdout().flush();
+
+    ::mpi_sys::MPI_Init(::std::ptr::null_mut(), ::std::ptr::null_mut());
+    let (mut rank, mut size) = (0, 0);
+    ::mpi_sys::MPI_Comm_rank(::mpi_sys::RSMPI_COMM_WORLD, &mut rank);
+    ::mpi_sys::MPI_Comm_size(::mpi_sys::RSMPI_COMM_WORLD, &mut size);
+    (rank, size)
+}
+
+#[cfg(feature = "mpi")]
+fn check_mpi(doctor: &mut Doctor, rank: c_int, size: c_int) {
+    match size {
+        n if n < 1 => doctor.fail(&format!("MPI reported a communicator of size {}", n), "\
+            MPI_COMM_WORLD is broken.  This usually means that mpi-sys and liblammps \
+            were linked against different implementations of MPI.  Check `mpicc -show` \
+            against `ldd` on liblammps.\
+        "),
+        1 => {
+            doctor.ok(&format!("rank {} of {}", rank, size));
+            doctor.info("(run this binary under `mpirun -np 2` to test communication between ranks)");
+        },
+        _ => doctor.ok(&format!("rank {} of {}", rank, size)),
+    }
+}
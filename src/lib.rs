@@ -2,18 +2,84 @@
 #![doc(html_root_url = "https://docs.rs/lammps-sys/0.6.0")]
 
 //! Automatically-generated bindings for lammps, using bindgen.
+//!
+//! Functions that take a LAMMPS instance use the opaque [`lammps_instance`] type
+//! rather than `void *`.  See its documentation for help porting older code.
+//!
+//! [`lammps_instance`]: struct.lammps_instance.html
+//!
+//! A safe interface is available in the [`safe`] module.
+//!
+//! [`safe`]: safe/index.html
 
 #[cfg(feature = "mpi")]
 extern crate mpi_sys;
 
+use ::std::os::raw::c_void;
+
+pub mod safe;
+
+/// Opaque type for a LAMMPS instance (an object of type `LAMMPS_NS::LAMMPS`).
+///
+/// In LAMMPS' `library.h`, instances are passed around as `void *`.  The bindings
+/// generated by this crate replace these with `*mut lammps_instance`, which is
+/// ABI-identical but prevents you from e.g. accidentally supplying the address
+/// of the variable that holds the handle.
+///
+/// Code written against older versions of `lammps-sys` can use
+/// [`from_void`](#method.from_void) and friends to convert.
+#[repr(C)]
+pub struct lammps_instance {
+    _private: [u8; 0],
+}
+
+impl lammps_instance {
+    /// Cast an untyped instance pointer.
+    pub fn from_void(ptr: *mut c_void) -> *mut lammps_instance
+    { ptr as *mut lammps_instance }
+
+    /// Cast an instance pointer back to `void *`.
+    pub fn to_void(ptr: *mut lammps_instance) -> *mut c_void
+    { ptr as *mut c_void }
+
+    /// Cast the output argument of e.g. `lammps_open_no_mpi`.
+    ///
+    /// This lets you continue to write `&mut lmp` where `lmp: *mut c_void`.
+    pub fn from_void_out(ptr: *mut *mut c_void) -> *mut *mut lammps_instance
+    { ptr as *mut *mut lammps_instance }
+}
+
 #[cfg(feature = "mpi")]
 extern "C" {
     pub fn lammps_open(
         argc: std::os::raw::c_int,
         argv: *mut *mut ::std::os::raw::c_char,
         communicator: mpi_sys::MPI_Comm,
-        lmp: *mut *mut ::std::os::raw::c_void,
+        lmp: *mut *mut lammps_instance,
     );
 }
 
+/// Information about the LAMMPS library that this crate was built against.
+///
+/// This is recorded by the build script.  The `lammps-sys-doctor` example
+/// uses it to check that the library loaded at runtime is the one that was linked.
+#[derive(Debug, Copy, Clone)]
+pub struct BuildInfo {
+    /// How LAMMPS was obtained: one of `"system"`, `"build"`, `"build-dir"` or `"none"`.
+    ///
+    /// (see `RUST_LAMMPS_SOURCE` and `RUST_LAMMPS_BUILD_DIR` in the README)
+    pub source: &'static str,
+    /// Path to the library file that was linked, if known.
+    pub library: Option<&'static str>,
+    /// Path to the `lmp` executable built from the same configuration as the library.
+    ///
+    /// This is only available with the `exe` feature.
+    pub exe: Option<&'static str>,
+    /// The LAMMPS packages requested through cargo features, e.g. `"USER-OMP"`.
+    pub packages: &'static [&'static str],
+}
+
+/// Information about the LAMMPS library that this crate was built against.
+pub const BUILD_INFO: BuildInfo = include!(concat!(env!("OUT_DIR"), "/codegen/build_info.rs"));
+
 include!(concat!(env!("OUT_DIR"), "/codegen/lammps.rs"));
@@ -0,0 +1,214 @@
+use ::std::mem;
+use ::std::ptr;
+use ::std::os::raw::{c_char, c_void};
+use ::std::slice;
+use super::{c_string, Lammps, LammpsError, Severity};
+
+/// LAMMPS' `tagint`, the type of atom IDs.
+///
+/// This is `i64` if LAMMPS was compiled with `-DLAMMPS_BIGBIG`, and `i32` otherwise.
+#[cfg(lammps_bigbig)]
+pub type TagInt = i64;
+/// LAMMPS' `tagint`, the type of atom IDs.
+///
+/// This is `i64` if LAMMPS was compiled with `-DLAMMPS_BIGBIG`, and `i32` otherwise.
+#[cfg(not(lammps_bigbig))]
+pub type TagInt = i32;
+
+/// LAMMPS' `imageint`, which holds packed image flags.
+///
+/// See [`pack_image`] and [`unpack_image`].
+///
+/// [`pack_image`]: fn.pack_image.html
+/// [`unpack_image`]: fn.unpack_image.html
+#[cfg(lammps_bigbig)]
+pub type ImageInt = i64;
+/// LAMMPS' `imageint`, which holds packed image flags.
+///
+/// See [`pack_image`] and [`unpack_image`].
+///
+/// [`pack_image`]: fn.pack_image.html
+/// [`unpack_image`]: fn.unpack_image.html
+#[cfg(not(lammps_bigbig))]
+pub type ImageInt = i32;
+
+// IMGBITS and IMGMAX from lmptype.h
+#[cfg(lammps_bigbig)]
+const IMG_BITS: u32 = 21;
+#[cfg(not(lammps_bigbig))]
+const IMG_BITS: u32 = 10;
+const IMG_MAX: ImageInt = 1 << (IMG_BITS - 1);
+const IMG_MASK: ImageInt = (1 << IMG_BITS) - 1;
+
+/// Pack image flags into an `imageint`, like LAMMPS does.
+pub fn pack_image(image: [i32; 3]) -> ImageInt {
+    let field = |i: i32| (ImageInt::from(i) + IMG_MAX) & IMG_MASK;
+    field(image[0]) | (field(image[1]) << IMG_BITS) | (field(image[2]) << (2 * IMG_BITS))
+}
+
+/// Unpack the image flags in an `imageint`.
+#[allow(clippy::unnecessary_cast)] // (not unnecessary under LAMMPS_BIGBIG)
+pub fn unpack_image(image: ImageInt) -> [i32; 3] {
+    let field = |shift: u32| (((image >> shift) & IMG_MASK) - IMG_MAX) as i32;
+    [field(0), field(IMG_BITS), field(2 * IMG_BITS)]
+}
+
+/// # Per-atom data
+///
+/// These methods borrow LAMMPS' own arrays for the atoms owned by this process,
+/// in whatever order LAMMPS currently keeps them.  Because anything that runs a
+/// command may sort or reallocate these arrays, the borrow checker will not let
+/// the slices outlive the borrow of the instance.
+///
+/// For data from all processes, ordered by atom ID, see the `gather_*` methods.
+impl Lammps {
+    /// The number of atoms owned by this process.
+    pub fn nlocal(&self) -> Result<usize, LammpsError> { self.atom_count("nlocal") }
+
+    /// The number of ghost atoms on this process.
+    ///
+    /// LAMMPS cannot report this before 2020, so this (and the `*_with_ghosts`
+    /// methods) will fail on older versions.
+    pub fn nghost(&self) -> Result<usize, LammpsError> { self.atom_count("nghost") }
+
+    /// Positions of local atoms.  (`x`)
+    pub fn positions(&self) -> Result<&[[f64; 3]], LammpsError> {
+        self.vectors("x", false).map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Positions of local atoms, followed by ghost atoms.
+    pub fn positions_with_ghosts(&self) -> Result<&[[f64; 3]], LammpsError> {
+        self.vectors("x", true).map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Velocities of local atoms.  (`v`)
+    pub fn velocities(&self) -> Result<&[[f64; 3]], LammpsError> {
+        self.vectors("v", false).map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Forces on local atoms.  (`f`)
+    pub fn forces(&self) -> Result<&[[f64; 3]], LammpsError> {
+        self.vectors("f", false).map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Forces on local atoms, for modification.
+    ///
+    /// Changes are only meaningful where LAMMPS won't recompute the forces first,
+    /// e.g. from a callback of `fix external`.
+    pub fn forces_mut(&mut self) -> Result<&mut [[f64; 3]], LammpsError> {
+        self.vectors("f", false).map(|(ptr, len)| unsafe { slice_from_mut(ptr, len) })
+    }
+
+    /// Atom types of local atoms.  (`type`)
+    pub fn types(&self) -> Result<&[i32], LammpsError> {
+        self.scalars("type", false, mem::size_of::<i32>(), None)
+            .map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Atom types of local atoms, followed by ghost atoms.
+    pub fn types_with_ghosts(&self) -> Result<&[i32], LammpsError> {
+        self.scalars("type", true, mem::size_of::<i32>(), None)
+            .map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Atom IDs of local atoms.  (`id`)
+    pub fn tags(&self) -> Result<&[TagInt], LammpsError> {
+        self.scalars("id", false, mem::size_of::<TagInt>(), Some("tagint"))
+            .map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Atom IDs of local atoms, followed by ghost atoms.
+    pub fn tags_with_ghosts(&self) -> Result<&[TagInt], LammpsError> {
+        self.scalars("id", true, mem::size_of::<TagInt>(), Some("tagint"))
+            .map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    /// Packed image flags of local atoms.  (`image`)
+    ///
+    /// Use [`unpack_image`](fn.unpack_image.html) to read them.
+    pub fn images(&self) -> Result<&[ImageInt], LammpsError> {
+        self.scalars("image", false, mem::size_of::<ImageInt>(), Some("imageint"))
+            .map(|(ptr, len)| unsafe { slice_from(ptr, len) })
+    }
+
+    // Read `nlocal` or `nghost`.  Older versions only have `nlocal`, and only as a global;
+    // newer ones have both as globals and as settings.
+    fn atom_count(&self, name: &str) -> Result<usize, LammpsError> {
+        self.check_usable()?;
+        let count = match self.extract_global::<i32>(name) {
+            Ok(n) => Some(n),
+            Err(_) => self.extract_setting(name),
+        };
+        match count {
+            Some(n) if n >= 0 => Ok(n as usize),
+            _ => Err(LammpsError::new(Severity::Error, format!(
+                "could not get '{}' from LAMMPS (it may not be supported by this version)", name,
+            ))),
+        }
+    }
+
+    fn view_len(&self, ghosts: bool) -> Result<usize, LammpsError> {
+        let nlocal = self.nlocal()?;
+        match ghosts {
+            true => Ok(nlocal + self.nghost()?),
+            false => Ok(nlocal),
+        }
+    }
+
+    // Get a per-atom `double **` (which LAMMPS allocates contiguously) and its length.
+    fn vectors(&self, name: &str, ghosts: bool) -> Result<(*mut [f64; 3], usize), LammpsError> {
+        let len = self.view_len(ghosts)?;
+        let ptr = self.extract_atom(name)? as *mut *mut f64;
+        match ptr.is_null() || len == 0 {
+            true => Ok((ptr::null_mut(), 0)),
+            false => Ok((unsafe { *ptr } as *mut [f64; 3], len)),
+        }
+    }
+
+    // Get a per-atom `int *`, `tagint *` or `imageint *` and its length.
+    fn scalars<T>(&self, name: &str, ghosts: bool, size: usize, setting: Option<&str>) -> Result<(*mut T, usize), LammpsError> {
+        if let Some(setting) = setting {
+            self.check_int_size(setting, size)?;
+        }
+        let len = self.view_len(ghosts)?;
+        let ptr = self.extract_atom(name)? as *mut T;
+        match ptr.is_null() || len == 0 {
+            true => Ok((ptr::null_mut(), 0)),
+            false => Ok((ptr, len)),
+        }
+    }
+
+    /// Make sure that LAMMPS agrees with the size of one of our integer types.
+    ///
+    /// (the build script sets these from the defines of the library, which could be wrong)
+    pub(crate) fn check_int_size(&self, setting: &str, size: usize) -> Result<(), LammpsError> {
+        match self.extract_setting(setting) {
+            Some(actual) if actual as usize != size => Err(LammpsError::invalid(format!(
+                "LAMMPS has a {}-byte {}, but lammps-sys was built for {} bytes (is LAMMPS_SIZES correct?)",
+                actual, setting, size,
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn extract_atom(&self, name: &str) -> Result<*mut c_void, LammpsError> {
+        self.check_usable()?;
+        let name = c_string(name)?;
+        Ok(unsafe { ::lammps_extract_atom(self.ptr, name.as_ptr() as *mut c_char) })
+    }
+}
+
+// (slice::from_raw_parts does not allow null pointers, even for empty slices)
+unsafe fn slice_from<'a, T>(ptr: *mut T, len: usize) -> &'a [T] {
+    match ptr.is_null() {
+        true => &[],
+        false => slice::from_raw_parts(ptr, len),
+    }
+}
+
+unsafe fn slice_from_mut<'a, T>(ptr: *mut T, len: usize) -> &'a mut [T] {
+    match ptr.is_null() {
+        true => &mut [],
+        false => slice::from_raw_parts_mut(ptr, len),
+    }
+}
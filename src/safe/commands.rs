@@ -0,0 +1,88 @@
+use ::std::os::raw::{c_char, c_int};
+use super::{c_string, Lammps, LammpsError, Severity};
+
+/// Size of the buffer for `lammps_get_last_error_message`.
+///
+/// Longer messages are truncated.
+#[cfg(feature = "exceptions")]
+const ERROR_BUFFER_LEN: usize = 4096;
+
+impl Lammps {
+    /// Execute a single command, like a line of an input script.
+    ///
+    /// For multiple commands, use [`commands_list`] or [`commands_string`].
+    /// Without the `exceptions` feature, an error in LAMMPS will exit the process.
+    ///
+    /// [`commands_list`]: #method.commands_list
+    /// [`commands_string`]: #method.commands_string
+    pub fn command(&mut self, cmd: &str) -> Result<(), LammpsError> {
+        self.check_usable()?;
+        if cmd.contains('\n') {
+            return Err(LammpsError::invalid(format!("command contains a newline: {:?}", cmd)));
+        }
+        let cmd = c_string(cmd)?;
+        unsafe { ::lammps_command(self.ptr, cmd.as_ptr() as *mut c_char); }
+        self.last_error()
+    }
+
+    /// Execute a list of commands, stopping at the first error.
+    ///
+    /// Each command may also end with `&` to continue onto the next one.
+    pub fn commands_list<S: AsRef<str>>(&mut self, cmds: &[S]) -> Result<(), LammpsError> {
+        self.check_usable()?;
+        let cmds = {
+            cmds.iter()
+                .map(|cmd| c_string(cmd.as_ref()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let mut ptrs: Vec<_> = cmds.iter().map(|s| s.as_ptr() as *mut c_char).collect();
+        // (`char **` before 2020, `const char **` after)
+        unsafe { ::lammps_commands_list(self.ptr, ptrs.len() as c_int, ptrs.as_mut_ptr() as _); }
+        self.last_error()
+    }
+
+    /// Execute multiple commands separated by newlines, stopping at the first error.
+    pub fn commands_string(&mut self, cmds: &str) -> Result<(), LammpsError> {
+        self.check_usable()?;
+        let cmds = c_string(cmds)?;
+        unsafe { ::lammps_commands_string(self.ptr, cmds.as_ptr() as *mut c_char); }
+        self.last_error()
+    }
+
+    /// Fail if a previous error has left the instance unusable.
+    pub(crate) fn check_usable(&self) -> Result<(), LammpsError> {
+        match self.aborted.get() {
+            true => Err(LammpsError::new(Severity::Abort, "this LAMMPS instance was aborted by a previous error")),
+            false => Ok(()),
+        }
+    }
+
+    /// Collect (and clear) the error from the last call into LAMMPS.
+    #[cfg(feature = "exceptions")]
+    pub(crate) fn last_error(&self) -> Result<(), LammpsError> {
+        use ::std::ffi::CStr;
+
+        unsafe {
+            if ::lammps_has_error(self.ptr) == 0 {
+                return Ok(());
+            }
+            let mut buf = vec![0 as c_char; ERROR_BUFFER_LEN];
+            let code = ::lammps_get_last_error_message(self.ptr, buf.as_mut_ptr(), buf.len() as c_int);
+            let text = CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned();
+
+            // 1 is a normal error, 2 is an abort
+            let severity = match code {
+                2 => Severity::Abort,
+                _ => Severity::Error,
+            };
+            if severity == Severity::Abort {
+                self.aborted.set(true);
+            }
+            Err(LammpsError::parse(severity, &text))
+        }
+    }
+
+    /// Without exceptions, we never get to see an error.
+    #[cfg(not(feature = "exceptions"))]
+    pub(crate) fn last_error(&self) -> Result<(), LammpsError> { Ok(()) }
+}
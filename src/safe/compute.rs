@@ -0,0 +1,199 @@
+use ::std::os::raw::{c_char, c_int, c_void};
+use ::std::slice;
+use super::{c_string, Lammps, LammpsError, Severity};
+
+// style and type codes for lammps_extract_compute and lammps_extract_fix
+const STYLE_GLOBAL: c_int = 0;
+const STYLE_ATOM: c_int = 1;
+const TYPE_SCALAR: c_int = 0;
+const TYPE_VECTOR: c_int = 1;
+const TYPE_ARRAY: c_int = 2;
+const TYPE_SIZE_VECTOR: c_int = 3;
+const TYPE_SIZE_ROWS: c_int = 4;
+const TYPE_SIZE_COLS: c_int = 5;
+
+/// The first version whose `lammps_extract_compute` can report the sizes of vectors and arrays.
+const SIZE_QUERY_VERSION: i32 = 20201029;
+
+/// # Computes and fixes
+///
+/// These evaluate the compute or fix if necessary, and copy out the result.
+/// Computes generally can't be evaluated before the first `run`.
+///
+/// Older versions of LAMMPS cannot report the size of the global vectors and arrays
+/// of computes, or the number of columns of per-atom arrays.  Before 29Oct2020
+/// (and so with the bundled 7Aug2019), [`compute_vector`], [`compute_array`] and
+/// [`compute_peratom_array`] always fail; use an equal-style variable that refers
+/// to the elements (e.g. `c_ID[2]`) instead.
+///
+/// [`compute_vector`]: #method.compute_vector
+/// [`compute_array`]: #method.compute_array
+/// [`compute_peratom_array`]: #method.compute_peratom_array
+impl Lammps {
+    /// The global scalar of a compute.
+    pub fn compute_scalar(&mut self, id: &str) -> Result<f64, LammpsError> {
+        let ptr = self.extract_compute(id, STYLE_GLOBAL, TYPE_SCALAR, "a global scalar")?;
+        Ok(unsafe { *(ptr as *const f64) })
+    }
+
+    /// The global vector of a compute.
+    ///
+    /// This requires LAMMPS 29Oct2020 or later, which can report the length of the vector.
+    pub fn compute_vector(&mut self, id: &str) -> Result<Vec<f64>, LammpsError> {
+        let len = self.compute_size(id, STYLE_GLOBAL, TYPE_SIZE_VECTOR)?;
+        let ptr = self.extract_compute(id, STYLE_GLOBAL, TYPE_VECTOR, "a global vector")?;
+        Ok(unsafe { slice::from_raw_parts(ptr as *const f64, len) }.to_vec())
+    }
+
+    /// The global array of a compute, as a list of rows.
+    ///
+    /// This requires LAMMPS 29Oct2020 or later, which can report the size of the array.
+    pub fn compute_array(&mut self, id: &str) -> Result<Vec<Vec<f64>>, LammpsError> {
+        let rows = self.compute_size(id, STYLE_GLOBAL, TYPE_SIZE_ROWS)?;
+        let cols = self.compute_size(id, STYLE_GLOBAL, TYPE_SIZE_COLS)?;
+        let ptr = self.extract_compute(id, STYLE_GLOBAL, TYPE_ARRAY, "a global array")?;
+        Ok(unsafe { copy_rows(ptr as *const *const f64, rows, cols) })
+    }
+
+    /// The per-atom vector of a compute, for the atoms owned by this process.
+    pub fn compute_peratom(&mut self, id: &str) -> Result<Vec<f64>, LammpsError> {
+        let len = self.nlocal()?;
+        let ptr = self.extract_compute_peratom(id, TYPE_VECTOR, len, "a per-atom vector")?;
+        Ok(unsafe { copy_slice(ptr as *const f64, len) })
+    }
+
+    /// The per-atom array of a compute, for the atoms owned by this process.
+    ///
+    /// This requires LAMMPS 29Oct2020 or later, which can report the number of columns.
+    pub fn compute_peratom_array(&mut self, id: &str) -> Result<Vec<Vec<f64>>, LammpsError> {
+        let cols = self.compute_size(id, STYLE_ATOM, TYPE_SIZE_COLS)?;
+        let rows = self.nlocal()?;
+        let ptr = self.extract_compute_peratom(id, TYPE_ARRAY, rows, "a per-atom array")?;
+        Ok(unsafe { copy_rows(ptr as *const *const f64, rows, cols) })
+    }
+
+    /// The global scalar of a fix.
+    pub fn fix_scalar(&mut self, id: &str) -> Result<f64, LammpsError> {
+        self.extract_fix_global(id, TYPE_SCALAR, 0, 0, "a global scalar")
+    }
+
+    /// An element of the global vector of a fix, counting from 0.
+    ///
+    /// This is evaluated as the formula `f_ID[i+1]`, so that LAMMPS checks the index.
+    pub fn fix_vector(&mut self, id: &str, i: usize) -> Result<f64, LammpsError> {
+        let id = check_id(id)?;
+        self.eval(&format!("f_{}[{}]", id, i as u64 + 1))
+    }
+
+    /// An element of the global array of a fix, counting from 0.
+    ///
+    /// This is evaluated as the formula `f_ID[i+1][j+1]`, so that LAMMPS checks the indices.
+    pub fn fix_array(&mut self, id: &str, i: usize, j: usize) -> Result<f64, LammpsError> {
+        let id = check_id(id)?;
+        self.eval(&format!("f_{}[{}][{}]", id, i as u64 + 1, j as u64 + 1))
+    }
+
+    /// The per-atom vector of a fix, for the atoms owned by this process.
+    pub fn fix_peratom(&mut self, id: &str) -> Result<Vec<f64>, LammpsError> {
+        let len = self.nlocal()?;
+        let ptr = self.extract_fix_raw(id, STYLE_ATOM, TYPE_VECTOR, 0, 0)?;
+        // (checked after extracting, because the fix may need to communicate)
+        if len == 0 {
+            return Ok(vec![]);
+        }
+        let ptr = non_null(ptr, || missing("fix", id, "a per-atom vector"))?;
+        Ok(unsafe { copy_slice(ptr as *const f64, len) })
+    }
+
+    // Global values of fixes are copied into memory that we must free.
+    // (the indices are not checked by LAMMPS)
+    fn extract_fix_global(&mut self, id: &str, ty: c_int, i: usize, j: usize, what: &str) -> Result<f64, LammpsError> {
+        let ptr = self.extract_fix(id, STYLE_GLOBAL, ty, i, j, what)?;
+        unsafe {
+            let value = *(ptr as *const f64);
+            ::lammps_free(ptr);
+            Ok(value)
+        }
+    }
+
+    fn extract_fix(&mut self, id: &str, style: c_int, ty: c_int, i: usize, j: usize, what: &str) -> Result<*mut c_void, LammpsError> {
+        let ptr = self.extract_fix_raw(id, style, ty, i, j)?;
+        non_null(ptr, || missing("fix", id, what))
+    }
+
+    fn extract_fix_raw(&mut self, id: &str, style: c_int, ty: c_int, i: usize, j: usize) -> Result<*mut c_void, LammpsError> {
+        self.check_usable()?;
+        let c_id = c_string(id)?;
+        let ptr = unsafe {
+            ::lammps_extract_fix(self.ptr, c_id.as_ptr() as *mut c_char, style, ty, i as c_int, j as c_int)
+        };
+        self.last_error()?;
+        Ok(ptr)
+    }
+
+    // This memory belongs to the compute.
+    fn extract_compute(&mut self, id: &str, style: c_int, ty: c_int, what: &str) -> Result<*mut c_void, LammpsError> {
+        let ptr = self.extract_compute_raw(id, style, ty)?;
+        non_null(ptr, || missing("compute", id, what))
+    }
+
+    // Per-atom data may be null on a process without atoms, so that is only an error if there are some.
+    // (checked after extracting, because the compute may need to communicate)
+    fn extract_compute_peratom(&mut self, id: &str, ty: c_int, nlocal: usize, what: &str) -> Result<*mut c_void, LammpsError> {
+        let ptr = self.extract_compute_raw(id, STYLE_ATOM, ty)?;
+        match nlocal {
+            0 => Ok(ptr),
+            _ => non_null(ptr, || missing("compute", id, what)),
+        }
+    }
+
+    fn extract_compute_raw(&mut self, id: &str, style: c_int, ty: c_int) -> Result<*mut c_void, LammpsError> {
+        self.check_usable()?;
+        let c_id = c_string(id)?;
+        let ptr = unsafe { ::lammps_extract_compute(self.ptr, c_id.as_ptr() as *mut c_char, style, ty) };
+        self.last_error()?;
+        Ok(ptr)
+    }
+
+    fn compute_size(&mut self, id: &str, style: c_int, ty: c_int) -> Result<usize, LammpsError> {
+        if self.version() < SIZE_QUERY_VERSION {
+            return Err(LammpsError::invalid(format!(
+                "LAMMPS {} cannot report the size of compute data; 29Oct2020 or later is required",
+                self.version(),
+            )));
+        }
+        let ptr = self.extract_compute(id, style, ty, "data of this shape")?;
+        Ok(unsafe { *(ptr as *const c_int) } as usize)
+    }
+}
+
+// IDs of computes and fixes follow the same rules as variable names.
+fn check_id(id: &str) -> Result<&str, LammpsError> {
+    match !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        true => Ok(id),
+        false => Err(LammpsError::invalid(format!("invalid ID: {:?}", id))),
+    }
+}
+
+fn missing(kind: &str, id: &str, what: &str) -> String {
+    format!("{} '{}' does not exist or does not have {}", kind, id, what)
+}
+
+pub(crate) fn non_null<F: FnOnce() -> String>(ptr: *mut c_void, message: F) -> Result<*mut c_void, LammpsError> {
+    match ptr.is_null() {
+        true => Err(LammpsError::new(Severity::Error, message())),
+        false => Ok(ptr),
+    }
+}
+
+// (LAMMPS may leave per-atom data unallocated when there are no atoms)
+pub(crate) unsafe fn copy_slice(ptr: *const f64, len: usize) -> Vec<f64> {
+    match len {
+        0 => vec![],
+        _ => slice::from_raw_parts(ptr, len).to_vec(),
+    }
+}
+
+unsafe fn copy_rows(ptr: *const *const f64, rows: usize, cols: usize) -> Vec<Vec<f64>> {
+    (0..rows).map(|r| copy_slice(*ptr.add(r), cols)).collect()
+}
@@ -0,0 +1,197 @@
+use ::std::mem;
+use ::std::os::raw::c_int;
+use ::std::ptr;
+use super::{pack_image, ImageInt, Lammps, LammpsError, Severity, TagInt};
+
+// Before this version, lammps_create_atoms refuses to add atoms to a system whose
+// IDs are not consecutive.
+const ANY_IDS_VERSION: i32 = 20201029;
+
+/// An atom to be created by [`Lammps::create_atoms`].
+///
+/// [`Lammps::create_atoms`]: struct.Lammps.html#method.create_atoms
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtomSpec {
+    /// The atom ID.  If `None`, one is assigned.
+    pub id: Option<TagInt>,
+    /// The atom type, from 1 to the number of types.
+    pub atom_type: i32,
+    /// The position, which must be inside the box.
+    pub position: [f64; 3],
+    /// The velocity.  Zero by default.
+    pub velocity: [f64; 3],
+    /// Unpacked image flags.
+    pub image: [i32; 3],
+    /// The molecule ID, for atom styles that have them.
+    pub molecule: Option<TagInt>,
+}
+
+impl AtomSpec {
+    /// An atom at rest, with default image flags and no ID.
+    pub fn new(atom_type: i32, position: [f64; 3]) -> AtomSpec {
+        AtomSpec {
+            id: None,
+            atom_type,
+            position,
+            velocity: [0.0; 3],
+            image: [0; 3],
+            molecule: None,
+        }
+    }
+
+    /// Set the atom ID.
+    pub fn id(self, id: TagInt) -> Self { AtomSpec { id: Some(id), ..self } }
+
+    /// Set the velocity.
+    pub fn velocity(self, velocity: [f64; 3]) -> Self { AtomSpec { velocity, ..self } }
+
+    /// Set the (unpacked) image flags.
+    pub fn image(self, image: [i32; 3]) -> Self { AtomSpec { image, ..self } }
+
+    /// Set the molecule ID.
+    pub fn molecule(self, molecule: TagInt) -> Self { AtomSpec { molecule: Some(molecule), ..self } }
+}
+
+/// # Creating atoms
+impl Lammps {
+    /// Create atoms with `lammps_create_atoms`, returning their IDs.
+    ///
+    /// Either every atom or no atom must have an ID.  Given IDs must be unique and not
+    /// belong to existing atoms.  Without IDs, they are numbered after the largest existing ID.
+    /// (both checks require gathering all IDs if they are not consecutive, which is not
+    /// possible under `LAMMPS_BIGBIG`)  Under MPI, every process must call this with the
+    /// same atoms; each creates the ones in its own subdomain.
+    ///
+    /// Before LAMMPS 29Oct2020 (including the bundled 7Aug2019), atoms can only be added
+    /// while the existing IDs are consecutive (`1..=natoms`).
+    ///
+    /// The box must already exist, and every atom must be inside it.  Molecule IDs are
+    /// assigned afterwards with the `set` command.  If either of these fails, the error
+    /// is returned, but some or all of the atoms have already been created.
+    pub fn create_atoms(&mut self, atoms: &[AtomSpec]) -> Result<Vec<TagInt>, LammpsError> {
+        self.check_box_exists()?;
+        self.check_int_size("tagint", mem::size_of::<TagInt>())?;
+        self.check_int_size("imageint", mem::size_of::<ImageInt>())?;
+        if atoms.is_empty() {
+            return Ok(vec![]);
+        }
+        if atoms.len() > c_int::MAX as usize {
+            return Err(LammpsError::invalid(format!("too many atoms to create at once ({})", atoms.len())));
+        }
+        self.validate_atoms(atoms)?;
+
+        let natoms_before = self.natoms()?;
+        if natoms_before > 0 && self.version() < ANY_IDS_VERSION {
+            let natoms = self.gather_natoms()?;
+            if !self.ids_are_consecutive(natoms)? {
+                return Err(LammpsError::invalid(format!(
+                    "LAMMPS {} cannot add atoms while the existing IDs are not consecutive", self.version(),
+                )));
+            }
+        }
+        let mut ids: Vec<TagInt> = match atoms[0].id {
+            Some(_) => {
+                let ids: Vec<TagInt> = atoms.iter().map(|atom| atom.id.unwrap()).collect();
+                self.check_new_ids(&ids)?;
+                ids
+            },
+            None => {
+                let first = self.max_tag()? + 1;
+                (0..atoms.len()).map(|i| first + i as TagInt).collect()
+            },
+        };
+        let mut types: Vec<i32> = atoms.iter().map(|atom| atom.atom_type).collect();
+        let mut x: Vec<f64> = atoms.iter().flat_map(|atom| atom.position.to_vec()).collect();
+        let mut v: Vec<f64> = atoms.iter().flat_map(|atom| atom.velocity.to_vec()).collect();
+        let mut image: Vec<ImageInt> = atoms.iter().map(|atom| pack_image(atom.image)).collect();
+        let v_ptr = match atoms.iter().all(|atom| atom.velocity == [0.0; 3]) {
+            true => ptr::null_mut(),
+            false => v.as_mut_ptr(),
+        };
+
+        unsafe {
+            ::lammps_create_atoms(
+                self.ptr, atoms.len() as _, ids.as_mut_ptr() as _, types.as_mut_ptr(),
+                x.as_mut_ptr(), v_ptr, image.as_mut_ptr() as _, 0,
+            );
+        }
+        self.last_error()?;
+
+        let created = self.natoms()? - natoms_before;
+        if created != atoms.len() as i64 {
+            return Err(LammpsError::new(Severity::Error, format!(
+                "only {} of {} atoms were created; the others may be outside the box (or there is no box)",
+                created, atoms.len(),
+            )));
+        }
+
+        self.set_molecules(&ids, atoms)?;
+        Ok(ids)
+    }
+
+    fn check_new_ids(&self, ids: &[TagInt]) -> Result<(), LammpsError> {
+        let mut sorted = ids.to_vec();
+        sorted.sort();
+        if let Some(pair) = sorted.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(LammpsError::invalid(format!("atom ID {} is given more than once", pair[0])));
+        }
+        let exists = self.atom_exists()?;
+        match ids.iter().find(|&&id| exists(id)) {
+            Some(id) => Err(LammpsError::invalid(format!("there is already an atom with ID {}", id))),
+            None => Ok(()),
+        }
+    }
+
+    fn validate_atoms(&self, atoms: &[AtomSpec]) -> Result<(), LammpsError> {
+        if atoms.iter().any(|atom| atom.id.is_some() != atoms[0].id.is_some()) {
+            return Err(LammpsError::invalid("either all atoms or none of them must have an ID"));
+        }
+        let ntypes = self.extract_global::<i32>("ntypes")?;
+        for atom in atoms {
+            if atom.atom_type < 1 || atom.atom_type > ntypes {
+                return Err(LammpsError::invalid(format!(
+                    "atom type {} is out of range (there are {} types)", atom.atom_type, ntypes,
+                )));
+            }
+            if let Some(id) = atom.id {
+                if id <= 0 {
+                    return Err(LammpsError::invalid(format!("invalid atom ID: {}", id)));
+                }
+            }
+            if atom.position.iter().chain(&atom.velocity).any(|x| !x.is_finite()) {
+                return Err(LammpsError::invalid(format!("atom has a non-finite position or velocity: {:?}", atom)));
+            }
+        }
+        Ok(())
+    }
+
+    // Set molecule IDs, using ID ranges where possible to keep the number of commands down.
+    fn set_molecules(&mut self, ids: &[TagInt], atoms: &[AtomSpec]) -> Result<(), LammpsError> {
+        if atoms.iter().all(|atom| atom.molecule.is_none()) {
+            return Ok(());
+        }
+        // (the atoms exist by now, so this is only null if the atom style has no molecule IDs)
+        if self.extract_atom("molecule")?.is_null() {
+            return Err(LammpsError::invalid("the atoms were created, but the atom style does not have molecule IDs"));
+        }
+
+        let mut start = 0;
+        while start < atoms.len() {
+            let molecule = match atoms[start].molecule {
+                Some(molecule) => molecule,
+                None => { start += 1; continue; },
+            };
+            let mut end = start + 1;
+            while end < atoms.len() && atoms[end].molecule == Some(molecule) && ids[end] == ids[end - 1] + 1 {
+                end += 1;
+            }
+            let range = match end - start {
+                1 => format!("{}", ids[start]),
+                _ => format!("{}*{}", ids[start], ids[end - 1]),
+            };
+            self.command(&format!("set atom {} mol {}", range, molecule))?;
+            start = end;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use ::std::error::Error;
+use ::std::fmt;
+
+/// An error from a LAMMPS instance, or from a request that could not be given to one.
+///
+/// Errors from LAMMPS are parsed into their parts.  Their text looks like this:
+///
+/// ```text
+/// ERROR on proc 3: Lost atoms: original 4000 current 3998 (src/thermo.cpp:445)
+/// Last command: run 1000
+/// ```
+///
+/// Depending on the version of LAMMPS, some of these parts may be missing
+/// (e.g. older versions do not include the last command, and truncate long messages).
+#[derive(Debug, Clone)]
+pub struct LammpsError {
+    severity: Severity,
+    // the text as given by LAMMPS
+    text: String,
+    message: String,
+    rank: Option<u32>,
+    location: Option<(String, u32)>,
+    command: Option<String>,
+}
+
+/// How bad a [`LammpsError`] is.
+///
+/// [`LammpsError`]: struct.LammpsError.html
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Severity {
+    /// The request was rejected before it reached LAMMPS, and nothing happened.
+    ///
+    /// For instance, a string contained a NUL byte.
+    Invalid,
+    /// LAMMPS reported an error.  The instance can still be used.
+    Error,
+    /// LAMMPS reported an error that would normally call `MPI_Abort`, such as an
+    /// error that only occurred on some processes.
+    ///
+    /// The instance is no longer usable; all further operations on it will fail
+    /// with this severity, and it will not be closed when dropped.  The only
+    /// reasonable thing left to do is to exit (or call `MPI_Abort`).
+    Abort,
+}
+
+/// Common kinds of errors, as classified by [`LammpsError::category`].
+///
+/// These are recognized from the message, so they may not be detected for
+/// every version of LAMMPS.  More categories may be added in the future.
+///
+/// [`LammpsError::category`]: struct.LammpsError.html#method.category
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// `Lost atoms: original N current M`.  Usually means the simulation blew up.
+    LostAtoms,
+    /// `Out of range atoms - cannot compute PPPM` (or MSM).  Usually means the simulation blew up.
+    OutOfRangeAtoms,
+    /// `Non-numeric atom coords - simulation unstable`.
+    NonNumericCoords,
+    /// `Bond atoms N M missing` (or angle, dihedral, improper).
+    MissingBondAtoms,
+    /// The input contained a command that does not exist.
+    UnknownCommand,
+    /// A pair style that does not exist, or whose package is not installed.
+    UnknownPairStyle,
+    /// A fix style that does not exist, or whose package is not installed.
+    UnknownFixStyle,
+    /// A compute style that does not exist, or whose package is not installed.
+    UnknownComputeStyle,
+    /// `Illegal ... command`: a command had bad arguments.
+    IllegalCommand,
+    /// A variable was used that does not exist.
+    UnknownVariable,
+    /// `All pair coeffs are not set`.
+    PairCoeffsNotSet,
+    /// A file could not be opened.
+    CannotOpenFile,
+    /// The error was not created by LAMMPS; see [`Severity::Invalid`](enum.Severity.html#variant.Invalid).
+    Invalid,
+    /// Anything else.
+    Other,
+}
+
+// (prefix of the message, category), checked in order
+const CATEGORY_PREFIXES: &[(&str, ErrorCategory)] = &[
+    ("Lost atoms", ErrorCategory::LostAtoms),
+    ("Out of range atoms", ErrorCategory::OutOfRangeAtoms),
+    ("Non-numeric atom coords", ErrorCategory::NonNumericCoords),
+    ("Bond atoms", ErrorCategory::MissingBondAtoms),
+    ("Angle atoms", ErrorCategory::MissingBondAtoms),
+    ("Dihedral atoms", ErrorCategory::MissingBondAtoms),
+    ("Improper atoms", ErrorCategory::MissingBondAtoms),
+    ("Unknown command", ErrorCategory::UnknownCommand),
+    ("Unknown pair style", ErrorCategory::UnknownPairStyle),
+    ("Unrecognized pair style", ErrorCategory::UnknownPairStyle),
+    ("Unknown fix style", ErrorCategory::UnknownFixStyle),
+    ("Unrecognized fix style", ErrorCategory::UnknownFixStyle),
+    ("Unknown compute style", ErrorCategory::UnknownComputeStyle),
+    ("Unrecognized compute style", ErrorCategory::UnknownComputeStyle),
+    ("Illegal ", ErrorCategory::IllegalCommand),
+    ("Substitution for illegal variable", ErrorCategory::UnknownVariable),
+    ("Invalid variable name", ErrorCategory::UnknownVariable),
+    ("All pair coeffs are not set", ErrorCategory::PairCoeffsNotSet),
+    ("Cannot open", ErrorCategory::CannotOpenFile),
+];
+
+impl LammpsError {
+    pub(crate) fn new<S: Into<String>>(severity: Severity, message: S) -> LammpsError {
+        let message = message.into();
+        LammpsError {
+            severity,
+            text: message.clone(),
+            message,
+            rank: None,
+            location: None,
+            command: None,
+        }
+    }
+
+    pub(crate) fn invalid<S: Into<String>>(message: S) -> LammpsError {
+        LammpsError::new(Severity::Invalid, message)
+    }
+
+    /// Parse an error message written by LAMMPS.
+    #[cfg_attr(not(feature = "exceptions"), allow(dead_code))]
+    pub(crate) fn parse(severity: Severity, text: &str) -> LammpsError {
+        let text = text.trim();
+
+        let (head, command) = match text.find("Last command:") {
+            Some(i) => {
+                let command = text[i + "Last command:".len()..].trim();
+                let command = if command.is_empty() { None } else { Some(command.to_string()) };
+                (text[..i].trim(), command)
+            },
+            None => (text, None),
+        };
+
+        let (head, rank) = match () {
+            _ if head.starts_with("ERROR on proc ") => {
+                let rest = &head["ERROR on proc ".len()..];
+                match rest.find(':') {
+                    Some(i) => (rest[i + 1..].trim_start(), rest[..i].trim().parse().ok()),
+                    None => (head, None),
+                }
+            },
+            _ if head.starts_with("ERROR:") => (head["ERROR:".len()..].trim_start(), None),
+            _ => (head, None),
+        };
+
+        let (message, location) = match parse_location(head) {
+            Some((message, file, line)) => (message, Some((file.to_string(), line))),
+            None => (head, None),
+        };
+
+        LammpsError {
+            severity,
+            text: text.to_string(),
+            message: message.to_string(),
+            rank,
+            location,
+            command,
+        }
+    }
+
+    pub fn severity(&self) -> Severity { self.severity }
+
+    /// Can the instance still be used after this error?
+    pub fn is_recoverable(&self) -> bool { self.severity != Severity::Abort }
+
+    /// The error message, without any of the other parts.
+    pub fn message(&self) -> &str { &self.message }
+
+    /// The full text of the error, as written by LAMMPS.
+    pub fn text(&self) -> &str { &self.text }
+
+    /// The MPI rank, for errors that occurred on only one process (`ERROR on proc N`).
+    pub fn rank(&self) -> Option<u32> { self.rank }
+
+    /// The source file and line in LAMMPS that raised the error, e.g. `("src/thermo.cpp", 445)`.
+    pub fn location(&self) -> Option<(&str, u32)> {
+        self.location.as_ref().map(|&(ref file, line)| (&file[..], line))
+    }
+
+    /// The input command that LAMMPS was executing.
+    pub fn command(&self) -> Option<&str> { self.command.as_ref().map(|s| &s[..]) }
+
+    /// Classify the error by its message.
+    pub fn category(&self) -> ErrorCategory {
+        if self.severity == Severity::Invalid {
+            return ErrorCategory::Invalid;
+        }
+        CATEGORY_PREFIXES.iter()
+            .find(|&&(prefix, _)| self.message.starts_with(prefix))
+            .map_or(ErrorCategory::Other, |&(_, category)| category)
+    }
+}
+
+#[cfg_attr(not(feature = "exceptions"), allow(dead_code))]
+// Split "Lost atoms: original 4 current 3 (src/thermo.cpp:445)" into its message and location.
+fn parse_location(s: &str) -> Option<(&str, &str, u32)> {
+    if !s.ends_with(')') {
+        return None;
+    }
+    let open = s.rfind(" (")?;
+    let inside = &s[open + 2..s.len() - 1];
+    let colon = inside.rfind(':')?;
+    let line = inside[colon + 1..].parse().ok()?;
+    Some((s[..open].trim_end(), &inside[..colon], line))
+}
+
+impl fmt::Display for LammpsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Error for LammpsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_error() {
+        let e = LammpsError::parse(Severity::Error, "\
+            ERROR: Unknown command: foo bar (src/input.cpp:232)\n\
+            Last command: foo bar\n\
+        ");
+        assert_eq!(e.message(), "Unknown command: foo bar");
+        assert_eq!(e.rank(), None);
+        assert_eq!(e.location(), Some(("src/input.cpp", 232)));
+        assert_eq!(e.command(), Some("foo bar"));
+        assert_eq!(e.category(), ErrorCategory::UnknownCommand);
+        assert_eq!(e.to_string(), e.text());
+    }
+
+    #[test]
+    fn parse_error_on_proc() {
+        let e = LammpsError::parse(Severity::Abort, "\
+            ERROR on proc 3: Lost atoms: original 4000 current 3998 (../thermo.cpp:445)\
+        ");
+        assert_eq!(e.message(), "Lost atoms: original 4000 current 3998");
+        assert_eq!(e.rank(), Some(3));
+        assert_eq!(e.location(), Some(("../thermo.cpp", 445)));
+        assert_eq!(e.command(), None);
+        assert_eq!(e.category(), ErrorCategory::LostAtoms);
+        assert!(!e.is_recoverable());
+    }
+
+    #[test]
+    fn parse_truncated() {
+        // older versions cut long messages off at 100 characters, losing the location
+        let full = "\
+            ERROR: Substitution for illegal variable a_rather_long_variable_name_that_goes_on_and_on_and_on \
+            (../input.cpp:538)\
+        ";
+        let e = LammpsError::parse(Severity::Error, &full[..100]);
+        assert_eq!(e.message(), &full["ERROR: ".len()..100]);
+        assert_eq!(e.location(), None);
+        assert_eq!(e.category(), ErrorCategory::UnknownVariable);
+    }
+
+    #[test]
+    fn parse_without_prefix() {
+        let e = LammpsError::parse(Severity::Error, "Cannot open file data.missing: No such file or directory");
+        assert_eq!(e.message(), "Cannot open file data.missing: No such file or directory");
+        assert_eq!(e.location(), None);
+        assert_eq!(e.category(), ErrorCategory::CannotOpenFile);
+    }
+
+    #[test]
+    fn categories() {
+        let category = |message| LammpsError::parse(Severity::Error, message).category();
+        assert_eq!(category("ERROR: Illegal fix nve command (../fix_nve.cpp:32)"), ErrorCategory::IllegalCommand);
+        assert_eq!(category("ERROR: Unrecognized pair style 'foo' (../force.cpp:262)"), ErrorCategory::UnknownPairStyle);
+        assert_eq!(category("ERROR: Unknown fix style foo (src/modify.cpp:880)"), ErrorCategory::UnknownFixStyle);
+        assert_eq!(category("ERROR: Bond atoms 12 13 missing on proc 0 at step 100"), ErrorCategory::MissingBondAtoms);
+        assert_eq!(category("ERROR: All pair coeffs are not set (../pair.cpp:230)"), ErrorCategory::PairCoeffsNotSet);
+        assert_eq!(category("ERROR: Something else entirely"), ErrorCategory::Other);
+        assert_eq!(LammpsError::invalid("Unknown command").category(), ErrorCategory::Invalid);
+    }
+}
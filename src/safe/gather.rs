@@ -0,0 +1,293 @@
+use ::std::os::raw::{c_char, c_int, c_void};
+use super::{c_string, Lammps, LammpsError, TagInt};
+
+/// A per-atom property that can be gathered from or scattered to all processes.
+///
+/// The implementors are marker types like [`Positions`], which are used with
+/// [`Lammps::gather`] and friends.
+///
+/// [`Positions`]: enum.Positions.html
+/// [`Lammps::gather`]: struct.Lammps.html#method.gather
+pub trait AtomProperty {
+    /// The value for a single atom.
+    type Value: Copy + Default;
+
+    #[doc(hidden)]
+    const NAME: &'static str;
+    // the type code for lammps_gather_atoms
+    #[doc(hidden)]
+    const IS_DOUBLE: bool;
+    // the number of ints or doubles in `Value`
+    #[doc(hidden)]
+    const COUNT: usize;
+    // a global flag that tells whether the atom style has this property
+    #[doc(hidden)]
+    const FLAG: Option<&'static str>;
+}
+
+macro_rules! atom_properties {
+    ($(
+        $(#[$meta:meta])*
+        $Name:ident: $Value:ty = ($name:expr, $is_double:expr, $count:expr, $flag:expr);
+    )*) => {$(
+        $(#[$meta])*
+        #[derive(Debug, Copy, Clone)]
+        pub enum $Name {}
+
+        impl AtomProperty for $Name {
+            type Value = $Value;
+            const NAME: &'static str = $name;
+            const IS_DOUBLE: bool = $is_double;
+            const COUNT: usize = $count;
+            const FLAG: Option<&'static str> = $flag;
+        }
+    )*};
+}
+
+atom_properties! {
+    /// Atom positions.  (`x`)
+    Positions: [f64; 3] = ("x", true, 3, None);
+    /// Atom velocities.  (`v`)
+    Velocities: [f64; 3] = ("v", true, 3, None);
+    /// Forces on atoms.  (`f`)
+    Forces: [f64; 3] = ("f", true, 3, None);
+    /// Atom types.  (`type`)
+    Types: i32 = ("type", false, 1, None);
+    /// Unpacked image flags.  (`image`)
+    Images: [i32; 3] = ("image", false, 3, None);
+    /// Atom charges.  (`q`; requires an atom style with charges)
+    Charges: f64 = ("q", true, 1, Some("q_flag"));
+}
+
+/// # Gathering and scattering
+///
+/// These copy a per-atom property of every atom on every process, in order of atom ID.
+/// They must be called on all processes at once.
+///
+/// Except for the `_subset` methods, they require the atom IDs to be consecutive
+/// (`1..=natoms`).  This is checked, since LAMMPS would only print a warning.
+/// The `_subset` methods check that every ID belongs to an atom, which requires
+/// gathering all IDs if they are not consecutive.
+///
+/// Newer versions of LAMMPS report the largest atom ID, which tells whether the IDs are
+/// consecutive.  Before 2020 (including the bundled 7Aug2019), this is instead checked by
+/// gathering the atom types, which doubles the communication done by each call.
+impl Lammps {
+    /// Gather a property of all atoms.
+    ///
+    /// The value for the atom with ID `n` is at index `n - 1`.
+    pub fn gather<P: AtomProperty>(&self) -> Result<Vec<P::Value>, LammpsError> {
+        self.check_property::<P>()?;
+        let natoms = self.gather_natoms()?;
+        if !self.ids_are_consecutive(natoms)? {
+            return Err(LammpsError::invalid("atom IDs are not consecutive; use the _subset methods"));
+        }
+        self.gather_unchecked::<P>(natoms)
+    }
+
+    /// Gather a property of the atoms with the given IDs, in that order.
+    pub fn gather_subset<P: AtomProperty>(&self, ids: &[TagInt]) -> Result<Vec<P::Value>, LammpsError> {
+        self.check_property::<P>()?;
+        let mut ids = self.check_ids(ids)?;
+        let mut out = vec![P::Value::default(); ids.len()];
+        let name = c_string(P::NAME)?;
+        unsafe {
+            ::lammps_gather_atoms_subset(
+                self.ptr, name.as_ptr() as *mut c_char, type_code::<P>(), P::COUNT as c_int,
+                ids.len() as c_int, ids.as_mut_ptr(), out.as_mut_ptr() as *mut c_void,
+            );
+        }
+        self.last_error()?;
+        Ok(out)
+    }
+
+    /// Set a property of all atoms.
+    ///
+    /// `values` must have an element for every atom, in order of atom ID.
+    pub fn scatter<P: AtomProperty>(&mut self, values: &[P::Value]) -> Result<(), LammpsError> {
+        self.check_property::<P>()?;
+        let natoms = self.gather_natoms()?;
+        if values.len() != natoms {
+            return Err(LammpsError::invalid(format!(
+                "expected {} values for '{}', got {}", natoms, P::NAME, values.len(),
+            )));
+        }
+        // (scattering does not fail loudly either)
+        if !self.ids_are_consecutive(natoms)? {
+            return Err(LammpsError::invalid("atom IDs are not consecutive; use the _subset methods"));
+        }
+
+        let mut values = values.to_vec();
+        let name = c_string(P::NAME)?;
+        unsafe {
+            ::lammps_scatter_atoms(
+                self.ptr, name.as_ptr() as *mut c_char, type_code::<P>(), P::COUNT as c_int,
+                values.as_mut_ptr() as *mut c_void,
+            );
+        }
+        self.last_error()
+    }
+
+    /// Set a property of the atoms with the given IDs.
+    pub fn scatter_subset<P: AtomProperty>(&mut self, ids: &[TagInt], values: &[P::Value]) -> Result<(), LammpsError> {
+        self.check_property::<P>()?;
+        if values.len() != ids.len() {
+            return Err(LammpsError::invalid(format!(
+                "got {} ids but {} values for '{}'", ids.len(), values.len(), P::NAME,
+            )));
+        }
+        let mut ids = self.check_ids(ids)?;
+        let mut values = values.to_vec();
+        let name = c_string(P::NAME)?;
+        unsafe {
+            ::lammps_scatter_atoms_subset(
+                self.ptr, name.as_ptr() as *mut c_char, type_code::<P>(), P::COUNT as c_int,
+                ids.len() as c_int, ids.as_mut_ptr(), values.as_mut_ptr() as *mut c_void,
+            );
+        }
+        self.last_error()
+    }
+
+    /// Positions of all atoms, in order of atom ID.
+    pub fn gather_positions(&self) -> Result<Vec<[f64; 3]>, LammpsError> { self.gather::<Positions>() }
+
+    /// Velocities of all atoms, in order of atom ID.
+    pub fn gather_velocities(&self) -> Result<Vec<[f64; 3]>, LammpsError> { self.gather::<Velocities>() }
+
+    /// Forces on all atoms, in order of atom ID.
+    pub fn gather_forces(&self) -> Result<Vec<[f64; 3]>, LammpsError> { self.gather::<Forces>() }
+
+    /// Types of all atoms, in order of atom ID.
+    pub fn gather_types(&self) -> Result<Vec<i32>, LammpsError> { self.gather::<Types>() }
+
+    /// Image flags of all atoms, in order of atom ID.
+    pub fn gather_images(&self) -> Result<Vec<[i32; 3]>, LammpsError> { self.gather::<Images>() }
+
+    /// Set the positions of all atoms, in order of atom ID.
+    pub fn scatter_positions(&mut self, values: &[[f64; 3]]) -> Result<(), LammpsError> { self.scatter::<Positions>(values) }
+
+    /// Set the velocities of all atoms, in order of atom ID.
+    pub fn scatter_velocities(&mut self, values: &[[f64; 3]]) -> Result<(), LammpsError> { self.scatter::<Velocities>(values) }
+
+    /// Set the types of all atoms, in order of atom ID.
+    pub fn scatter_types(&mut self, values: &[i32]) -> Result<(), LammpsError> { self.scatter::<Types>(values) }
+
+    /// Set the image flags of all atoms, in order of atom ID.
+    pub fn scatter_images(&mut self, values: &[[i32; 3]]) -> Result<(), LammpsError> { self.scatter::<Images>(values) }
+
+    // natoms, checked against the limits of the gather functions
+    pub(crate) fn gather_natoms(&self) -> Result<usize, LammpsError> {
+        let natoms = self.natoms()?;
+        if natoms > i64::from(c_int::MAX) {
+            return Err(LammpsError::invalid(format!("too many atoms to gather ({})", natoms)));
+        }
+        Ok(natoms as usize)
+    }
+
+    fn gather_unchecked<P: AtomProperty>(&self, natoms: usize) -> Result<Vec<P::Value>, LammpsError> {
+        let mut out = vec![P::Value::default(); natoms];
+        let name = c_string(P::NAME)?;
+        unsafe {
+            ::lammps_gather_atoms(
+                self.ptr, name.as_ptr() as *mut c_char, type_code::<P>(), P::COUNT as c_int,
+                out.as_mut_ptr() as *mut c_void,
+            );
+        }
+        self.last_error()?;
+        Ok(out)
+    }
+
+    // LAMMPS would silently gather zeros for a property that the atom style doesn't have.
+    // (the flag is checked rather than the array, which is also null on a process without atoms)
+    fn check_property<P: AtomProperty>(&self) -> Result<(), LammpsError> {
+        match P::FLAG {
+            Some(flag) if self.extract_global::<i32>(flag)? == 0 => Err(LammpsError::invalid(format!(
+                "the atom style does not have '{}'", P::NAME,
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn ids_are_consecutive(&self, natoms: usize) -> Result<bool, LammpsError> {
+        if let Some(max) = self.map_tag_max() {
+            return Ok(max == natoms as TagInt);
+        }
+        // LAMMPS leaves the buffer alone if they aren't, and a type is never 0
+        let types = self.gather_unchecked::<Types>(natoms)?;
+        Ok(!types.contains(&0))
+    }
+
+    /// The largest atom ID on any process, or 0 if there are no atoms.
+    pub(crate) fn max_tag(&self) -> Result<TagInt, LammpsError> {
+        if let Some(max) = self.map_tag_max() {
+            return Ok(max);
+        }
+        let natoms = self.gather_natoms()?;
+        match self.ids_are_consecutive(natoms)? {
+            true => Ok(natoms as TagInt),
+            false => Ok(self.gather_ids(natoms)?.into_iter().max().unwrap_or(0)),
+        }
+    }
+
+    // The largest atom ID, which newer versions of LAMMPS keep track of.
+    // (it is -1 if there is no atom map)
+    fn map_tag_max(&self) -> Option<TagInt> {
+        self.extract_global::<TagInt>("map_tag_max").ok().filter(|&max| max >= 0)
+    }
+
+    // LAMMPS does not check the IDs given to the _subset functions, and reads out of
+    // bounds for IDs past the largest one.
+    fn check_ids(&self, ids: &[TagInt]) -> Result<Vec<c_int>, LammpsError> {
+        let exists = self.atom_exists()?;
+        match ids.iter().find(|&&id| !exists(id)) {
+            Some(id) => Err(LammpsError::invalid(format!("there is no atom with ID {}", id))),
+            // (every existing ID fits, since there are at most c_int::MAX atoms)
+            None => Ok(ids.iter().map(|&id| tag_to_c_int(id)).collect()),
+        }
+    }
+
+    // A test for whether an atom with some ID exists, which requires gathering all IDs
+    // if they are not consecutive.
+    pub(crate) fn atom_exists(&self) -> Result<Box<dyn Fn(TagInt) -> bool>, LammpsError> {
+        let natoms = self.gather_natoms()?;
+        Ok(match self.ids_are_consecutive(natoms)? {
+            true => Box::new(move |id| 1 <= id && id <= natoms as TagInt),
+            false => {
+                let mut all = self.gather_ids(natoms)?;
+                all.sort();
+                Box::new(move |id| all.binary_search(&id).is_ok())
+            },
+        })
+    }
+
+    // The IDs of all atoms, in no particular order.
+    //
+    // (lammps_gather_atoms_concat reads `id` as an `int` array)
+    #[cfg(not(lammps_bigbig))]
+    fn gather_ids(&self, natoms: usize) -> Result<Vec<TagInt>, LammpsError> {
+        let mut out = vec![0; natoms];
+        let name = c_string("id")?;
+        unsafe {
+            ::lammps_gather_atoms_concat(
+                self.ptr, name.as_ptr() as *mut c_char, 0, 1, out.as_mut_ptr() as *mut c_void,
+            );
+        }
+        self.last_error()?;
+        Ok(out)
+    }
+
+    #[cfg(lammps_bigbig)]
+    fn gather_ids(&self, _natoms: usize) -> Result<Vec<TagInt>, LammpsError> {
+        Err(LammpsError::invalid("atom IDs that are not consecutive cannot be gathered under LAMMPS_BIGBIG"))
+    }
+}
+
+#[allow(clippy::unnecessary_cast)] // (not unnecessary under LAMMPS_BIGBIG)
+fn tag_to_c_int(id: TagInt) -> c_int { id as c_int }
+
+fn type_code<P: AtomProperty>() -> c_int {
+    match P::IS_DOUBLE {
+        true => 1,
+        false => 0,
+    }
+}
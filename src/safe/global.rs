@@ -0,0 +1,235 @@
+use ::std::ffi::CStr;
+use ::std::os::raw::{c_char, c_void};
+use super::{c_string, Lammps, LammpsError, Severity};
+
+/// The type of a global value, as returned by `lammps_extract_global`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum GlobalType {
+    Int,
+    Int64,
+    Double,
+    /// `int[3]`, e.g. `periodicity`.
+    Int3,
+    /// `double[3]`, e.g. `boxlo`.
+    Double3,
+    String,
+}
+
+/// A type that can be read with [`Lammps::extract_global`].
+///
+/// This trait is sealed; it is implemented for exactly the types that LAMMPS can
+/// return, since the pointer given to `read` is only valid for those.
+///
+/// [`Lammps::extract_global`]: struct.Lammps.html#method.extract_global
+pub trait GlobalValue: Sized + private::Sealed {
+    /// The type that LAMMPS must report for the value.
+    const TYPE: GlobalType;
+
+    #[doc(hidden)]
+    unsafe fn read(ptr: *const c_void) -> Self;
+}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for i32 {}
+    impl Sealed for i64 {}
+    impl Sealed for f64 {}
+    impl Sealed for [i32; 3] {}
+    impl Sealed for [f64; 3] {}
+    impl Sealed for String {}
+}
+
+impl GlobalValue for i32 {
+    const TYPE: GlobalType = GlobalType::Int;
+    unsafe fn read(ptr: *const c_void) -> Self { *(ptr as *const i32) }
+}
+
+impl GlobalValue for i64 {
+    const TYPE: GlobalType = GlobalType::Int64;
+    unsafe fn read(ptr: *const c_void) -> Self { *(ptr as *const i64) }
+}
+
+impl GlobalValue for f64 {
+    const TYPE: GlobalType = GlobalType::Double;
+    unsafe fn read(ptr: *const c_void) -> Self { *(ptr as *const f64) }
+}
+
+impl GlobalValue for [i32; 3] {
+    const TYPE: GlobalType = GlobalType::Int3;
+    unsafe fn read(ptr: *const c_void) -> Self { *(ptr as *const [i32; 3]) }
+}
+
+impl GlobalValue for [f64; 3] {
+    const TYPE: GlobalType = GlobalType::Double3;
+    unsafe fn read(ptr: *const c_void) -> Self { *(ptr as *const [f64; 3]) }
+}
+
+impl GlobalValue for String {
+    const TYPE: GlobalType = GlobalType::String;
+    unsafe fn read(ptr: *const c_void) -> Self {
+        CStr::from_ptr(ptr as *const c_char).to_string_lossy().into_owned()
+    }
+}
+
+// Globals of type `bigint`, whose size depends on how LAMMPS was compiled.
+const BIGINT_GLOBALS: &[&str] = &[
+    "ntimestep", "atimestep", "natoms", "nbonds", "nangles", "ndihedrals", "nimpropers",
+];
+
+// Everything else supported by the `lammps_extract_global` of stable_7Aug2019.
+//
+// Newer versions of LAMMPS can describe their own globals through
+// `lammps_extract_global_datatype`, which is used for names not listed here.
+const KNOWN_GLOBALS: &[(&str, GlobalType)] = &[
+    ("dt", GlobalType::Double),
+    ("atime", GlobalType::Double),
+    ("boxlo", GlobalType::Double3),
+    ("boxhi", GlobalType::Double3),
+    ("boxxlo", GlobalType::Double),
+    ("boxxhi", GlobalType::Double),
+    ("boxylo", GlobalType::Double),
+    ("boxyhi", GlobalType::Double),
+    ("boxzlo", GlobalType::Double),
+    ("boxzhi", GlobalType::Double),
+    ("periodicity", GlobalType::Int3),
+    ("triclinic", GlobalType::Int),
+    ("xy", GlobalType::Double),
+    ("xz", GlobalType::Double),
+    ("yz", GlobalType::Double),
+    ("nlocal", GlobalType::Int),
+    ("ntypes", GlobalType::Int),
+    ("q_flag", GlobalType::Int),
+    ("units", GlobalType::String),
+    // (not in stable_7Aug2019; extraction will fail there)
+    ("atom_style", GlobalType::String),
+    // unit conversion constants
+    ("boltz", GlobalType::Double),
+    ("hplanck", GlobalType::Double),
+    ("mvv2e", GlobalType::Double),
+    ("ftm2v", GlobalType::Double),
+    ("mv2d", GlobalType::Double),
+    ("nktv2p", GlobalType::Double),
+    ("qqr2e", GlobalType::Double),
+    ("qe2f", GlobalType::Double),
+    ("vxmu2f", GlobalType::Double),
+    ("xxt2kmu", GlobalType::Double),
+    ("dielectric", GlobalType::Double),
+    ("qqrd2e", GlobalType::Double),
+    ("e_mass", GlobalType::Double),
+    ("hhmrr2e", GlobalType::Double),
+    ("mvh2r", GlobalType::Double),
+    ("angstrom", GlobalType::Double),
+    ("femtosecond", GlobalType::Double),
+    ("qelectron", GlobalType::Double),
+];
+
+impl Lammps {
+    /// The current timestep number.
+    pub fn timestep(&self) -> Result<i64, LammpsError> { self.extract_bigint("ntimestep") }
+
+    /// The total number of atoms across all processes.
+    pub fn natoms(&self) -> Result<i64, LammpsError> { self.extract_bigint("natoms") }
+
+    /// The size of the timestep.
+    pub fn dt(&self) -> Result<f64, LammpsError> { self.extract_global("dt") }
+
+    /// The lower corner of the simulation box.
+    pub fn boxlo(&self) -> Result<[f64; 3], LammpsError> { self.extract_global("boxlo") }
+
+    /// The upper corner of the simulation box.
+    pub fn boxhi(&self) -> Result<[f64; 3], LammpsError> { self.extract_global("boxhi") }
+
+    /// The `units` style, e.g. `"metal"`.
+    pub fn units(&self) -> Result<String, LammpsError> { self.extract_global("units") }
+
+    /// The `atom_style`, e.g. `"atomic"`.
+    ///
+    /// This is not available before LAMMPS 2020.
+    pub fn atom_style(&self) -> Result<String, LammpsError> { self.extract_global("atom_style") }
+
+    /// Read a global value through `lammps_extract_global`, checking its type.
+    ///
+    /// The names understood by LAMMPS are listed in the documentation of
+    /// `lammps_extract_global` in `library.cpp`.  Values of type `bigint` (such as
+    /// `natoms`) are `i64`, unless LAMMPS was compiled with `-DLAMMPS_SMALLSMALL`.
+    pub fn extract_global<T: GlobalValue>(&self, name: &str) -> Result<T, LammpsError> {
+        self.check_usable()?;
+        let actual = match self.global_type(name) {
+            Some(ty) => ty,
+            None => return Err(LammpsError::invalid(format!("unknown global '{}'", name))),
+        };
+        if actual != T::TYPE {
+            return Err(LammpsError::invalid(format!(
+                "global '{}' has type {:?}, not {:?}", name, actual, T::TYPE,
+            )));
+        }
+        let ptr = self.extract_global_ptr(name)?;
+        Ok(unsafe { T::read(ptr) })
+    }
+
+    /// Determine the type of a global value, if known.
+    pub fn global_type(&self, name: &str) -> Option<GlobalType> {
+        if BIGINT_GLOBALS.contains(&name) {
+            return match self.extract_setting("bigint") {
+                Some(4) => Some(GlobalType::Int),
+                _ => Some(GlobalType::Int64),
+            };
+        }
+        if let Some(&(_, ty)) = KNOWN_GLOBALS.iter().find(|&&(known, _)| known == name) {
+            return Some(ty);
+        }
+        self.global_type_from_lammps(name)
+    }
+
+    #[cfg(has_lammps_extract_global_datatype)]
+    fn global_type_from_lammps(&self, name: &str) -> Option<GlobalType> {
+        let name = c_string(name).ok()?;
+        let code = unsafe { ::lammps_extract_global_datatype(self.ptr, name.as_ptr() as *mut c_char) };
+        // LAMMPS_INT, LAMMPS_DOUBLE, LAMMPS_INT64, LAMMPS_STRING.
+        // (the 2D types are never used for globals)
+        match code {
+            0 => Some(GlobalType::Int),
+            2 => Some(GlobalType::Double),
+            4 => Some(GlobalType::Int64),
+            6 => Some(GlobalType::String),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(has_lammps_extract_global_datatype))]
+    fn global_type_from_lammps(&self, _name: &str) -> Option<GlobalType> { None }
+
+    /// Query a setting through `lammps_extract_setting`, such as the size of `bigint`
+    /// (`"bigint"`).
+    ///
+    /// Only the integer sizes (`"bigint"`, `"tagint"` and `"imageint"`) are known to
+    /// every version; newer versions add others like `"dimension"` and `"nlocal"`.
+    /// Returns `None` for names that LAMMPS does not recognize.
+    pub fn extract_setting(&self, name: &str) -> Option<i32> {
+        let name = c_string(name).ok()?;
+        match unsafe { ::lammps_extract_setting(self.ptr, name.as_ptr() as *mut c_char) } {
+            -1 => None,
+            value => Some(value),
+        }
+    }
+
+    // Read a bigint global, whatever its size.
+    fn extract_bigint(&self, name: &str) -> Result<i64, LammpsError> {
+        match self.global_type(name) {
+            Some(GlobalType::Int) => self.extract_global::<i32>(name).map(i64::from),
+            _ => self.extract_global::<i64>(name),
+        }
+    }
+
+    fn extract_global_ptr(&self, name: &str) -> Result<*const c_void, LammpsError> {
+        let c_name = c_string(name)?;
+        let ptr = unsafe { ::lammps_extract_global(self.ptr, c_name.as_ptr() as *mut c_char) };
+        match ptr.is_null() {
+            true => Err(LammpsError::new(Severity::Error, format!(
+                "LAMMPS did not provide global '{}' (it may not be supported by this version)", name,
+            ))),
+            false => Ok(ptr as *const c_void),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! A safe interface to LAMMPS instances.
+//!
+//! This is a thin layer over the raw bindings that takes care of the tedious
+//! and error-prone parts, like building a C `argv` and closing the instance.
+//!
+//! ```rust,no_run
+//! use lammps_sys::safe::Lammps;
+//!
+//! let lmp = Lammps::builder()
+//!     .log_none()
+//!     .screen_none()
+//!     .var("temperature", 300)
+//!     .open()
+//!     .expect("could not start LAMMPS");
+//! # drop(lmp);
+//! ```
+//!
+//! # Errors and the `exceptions` feature
+//!
+//! Without the `exceptions` feature, LAMMPS handles errors by printing a message
+//! and calling `exit()` (or `MPI_Abort`), which terminates the whole process.  Methods
+//! that return a [`LammpsError`] can then only report problems that are caught on
+//! the rust side.  Enable `exceptions` if you need to recover from errors in LAMMPS.
+//!
+//! [`LammpsError`]: struct.LammpsError.html
+
+use ::std::cell::Cell;
+use ::std::ffi::CString;
+use ::std::os::raw::{c_char, c_int};
+use ::std::ptr;
+use ::lammps_instance;
+
+mod atoms;
+mod commands;
+mod compute;
+mod create;
+mod error;
+mod gather;
+mod global;
+mod simbox;
+mod variable;
+
+pub use self::atoms::{ImageInt, TagInt, pack_image, unpack_image};
+pub use self::create::AtomSpec;
+pub use self::error::{ErrorCategory, LammpsError, Severity};
+pub use self::gather::{AtomProperty, Charges, Forces, Images, Positions, Types, Velocities};
+pub use self::global::{GlobalType, GlobalValue};
+pub use self::simbox::SimBox;
+pub use self::variable::{quote, VariableInfo, VariableStyle};
+
+/// A LAMMPS instance, which is closed when dropped.
+///
+/// This type is neither `Send` nor `Sync`; LAMMPS is not safe to use from
+/// multiple threads, and under MPI it should stay on the thread that initialized MPI.
+///
+/// Use [`Lammps::builder`] to create one.
+///
+/// [`Lammps::builder`]: #method.builder
+pub struct Lammps {
+    // (the raw pointer is also what makes this !Send and !Sync)
+    ptr: *mut lammps_instance,
+    // set after an error with Severity::Abort
+    // (a Cell, because even read-only operations can fail this way)
+    aborted: Cell<bool>,
+}
+
+/// Configures the command line arguments for a new [`Lammps`].
+///
+/// See [the LAMMPS documentation](https://lammps.sandia.gov/doc/Run_options.html)
+/// for the meaning of each option.
+///
+/// [`Lammps`]: struct.Lammps.html
+#[derive(Debug, Clone)]
+pub struct LammpsBuilder {
+    args: Vec<String>,
+    // the first problem with an argument, reported by `open`
+    error: Option<LammpsError>,
+}
+
+/// Argument to [`LammpsBuilder::echo`](struct.LammpsBuilder.html#method.echo).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Echo {
+    None,
+    Screen,
+    Log,
+    Both,
+}
+
+impl Default for LammpsBuilder {
+    fn default() -> Self { LammpsBuilder::new() }
+}
+
+impl LammpsBuilder {
+    /// A builder with no arguments, so that LAMMPS will use its defaults.
+    ///
+    /// Same as [`Lammps::builder`](struct.Lammps.html#method.builder).
+    pub fn new() -> Self {
+        // argv[0]; LAMMPS skips it.
+        LammpsBuilder { args: vec![String::from("lammps")], error: None }
+    }
+
+    /// `-log none`: don't write `log.lammps`.
+    pub fn log_none(self) -> Self { self.arg("-log").arg("none") }
+
+    /// `-log <path>`
+    pub fn log<S: Into<String>>(self, path: S) -> Self { self.arg("-log").arg(path) }
+
+    /// `-screen none`: don't write to standard output.
+    pub fn screen_none(self) -> Self { self.arg("-screen").arg("none") }
+
+    /// `-screen <path>`
+    pub fn screen<S: Into<String>>(self, path: S) -> Self { self.arg("-screen").arg(path) }
+
+    /// `-echo <style>`: echo input commands.
+    pub fn echo(self, echo: Echo) -> Self {
+        self.arg("-echo").arg(match echo {
+            Echo::None => "none",
+            Echo::Screen => "screen",
+            Echo::Log => "log",
+            Echo::Both => "both",
+        })
+    }
+
+    /// `-var <name> <value>`: define an index-style variable.
+    ///
+    /// Values that begin with `-` cannot be given on the command line,
+    /// and will cause [`open`](#method.open) to fail.
+    pub fn var<S: Into<String>, V: ToString>(self, name: S, value: V) -> Self {
+        self.var_list(name, &[value])
+    }
+
+    /// `-var <name> <value1> <value2> ...`: define an index-style variable with multiple values.
+    pub fn var_list<S: Into<String>, V: ToString>(self, name: S, values: &[V]) -> Self {
+        let mut out = self.arg("-var").arg(name);
+        for value in values {
+            let value = value.to_string();
+            // LAMMPS would take it for the next option
+            if value.starts_with('-') && out.error.is_none() {
+                out.error = Some(LammpsError::invalid(format!(
+                    "-var values cannot begin with '-' (got {:?}); define the variable with a command instead",
+                    value,
+                )));
+            }
+            out = out.arg(value);
+        }
+        out
+    }
+
+    /// `-suffix <style>`: e.g. `"omp"` to use USER-OMP styles where available.
+    pub fn suffix<S: Into<String>>(self, suffix: S) -> Self { self.arg("-suffix").arg(suffix) }
+
+    /// `-nocite`: don't write `log.cite`.
+    pub fn nocite(self) -> Self { self.arg("-nocite") }
+
+    /// Add an arbitrary command line argument.
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    /// The arguments that will be given to LAMMPS, including `argv[0]`.
+    pub fn args(&self) -> &[String] { &self.args }
+
+    /// Create the instance.
+    ///
+    /// Under the `mpi` feature, this uses `MPI_COMM_WORLD`, and initializes MPI if necessary.
+    pub fn open(self) -> Result<Lammps, LammpsError> {
+        self.validate()?;
+        // (newer versions also return the instance)
+        self.open_with(|argc, argv, ptr| unsafe { ::lammps_open_no_mpi(argc, argv, ptr); })
+    }
+
+    /// Create the instance on the given communicator.
+    ///
+    /// # Safety
+    ///
+    /// MPI must already be initialized, and `comm` must be a valid communicator
+    /// that outlives the instance.
+    #[cfg(feature = "mpi")]
+    pub unsafe fn open_with_comm(self, comm: ::mpi_sys::MPI_Comm) -> Result<Lammps, LammpsError> {
+        self.validate()?;
+        self.open_with(|argc, argv, ptr| ::lammps_open(argc, argv, comm, ptr))
+    }
+
+    fn validate(&self) -> Result<(), LammpsError> {
+        match self.error {
+            Some(ref e) => Err(e.clone()),
+            None => Ok(()),
+        }
+    }
+
+    fn open_with<F>(self, open: F) -> Result<Lammps, LammpsError>
+    where F: FnOnce(c_int, *mut *mut c_char, *mut *mut lammps_instance),
+    {
+        let args = {
+            self.args.into_iter()
+                .map(|arg| c_string(&arg))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        // C programs are allowed to expect argv[argc] == NULL.
+        let mut argv: Vec<*mut c_char> = args.iter().map(|s| s.as_ptr() as *mut c_char).collect();
+        argv.push(ptr::null_mut());
+
+        let mut ptr = ptr::null_mut();
+        open(args.len() as c_int, argv.as_mut_ptr(), &mut ptr);
+        match ptr.is_null() {
+            true => Err(LammpsError::new(Severity::Error, "LAMMPS failed to start; see its error output")),
+            false => Ok(Lammps { ptr, aborted: Cell::new(false) }),
+        }
+    }
+}
+
+impl Lammps {
+    /// Begin configuring a new instance.
+    pub fn builder() -> LammpsBuilder { LammpsBuilder::new() }
+
+    /// Take ownership of an instance created through the raw bindings.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid instance that nothing else will close.
+    pub unsafe fn from_raw(ptr: *mut lammps_instance) -> Lammps { Lammps { ptr, aborted: Cell::new(false) } }
+
+    /// Get the raw instance pointer, for use with the raw bindings.
+    ///
+    /// The instance will still be closed when `self` is dropped.
+    pub fn as_ptr(&self) -> *mut lammps_instance { self.ptr }
+
+    /// Release ownership of the raw instance pointer without closing it.
+    pub fn into_raw(self) -> *mut lammps_instance {
+        let ptr = self.ptr;
+        ::std::mem::forget(self);
+        ptr
+    }
+
+    /// The version of LAMMPS, as a number like `20190807`.
+    pub fn version(&self) -> i32 {
+        unsafe { ::lammps_version(self.ptr) as i32 }
+    }
+}
+
+impl Drop for Lammps {
+    fn drop(&mut self) {
+        // After an abort, the processes may disagree about what state LAMMPS is in,
+        // and the collective operations in the destructor could deadlock.
+        if !self.aborted.get() {
+            unsafe { ::lammps_close(self.ptr); }
+        }
+    }
+}
+
+pub(crate) fn c_string(s: &str) -> Result<CString, LammpsError> {
+    CString::new(s).map_err(|_| LammpsError::invalid(format!("string contains a NUL byte: {:?}", s)))
+}
@@ -0,0 +1,324 @@
+use super::{Lammps, LammpsError, Severity};
+
+/// A simulation box, in LAMMPS' restricted triclinic form.
+///
+/// The cell vectors of a triclinic box are
+///
+/// ```text
+/// a = (lx,  0,  0)
+/// b = (xy, ly,  0)
+/// c = (xz, yz, lz)
+/// ```
+///
+/// where `lx = hi[0] - lo[0]` and so on.  See
+/// [the LAMMPS documentation](https://lammps.sandia.gov/doc/Howto_triclinic.html) for details.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SimBox {
+    /// The lower corner, `[xlo, ylo, zlo]`.
+    pub lo: [f64; 3],
+    /// The upper bounds, `[xhi, yhi, zhi]`.  For a triclinic box, this is not a corner.
+    pub hi: [f64; 3],
+    /// The tilt factors `[xy, xz, yz]`, or `None` for an orthogonal box.
+    pub tilt: Option<[f64; 3]>,
+    /// Whether each dimension is periodic.
+    pub periodic: [bool; 3],
+}
+
+impl SimBox {
+    /// An orthogonal box, periodic in all dimensions.
+    pub fn orthogonal(lo: [f64; 3], hi: [f64; 3]) -> SimBox {
+        SimBox { lo, hi, tilt: None, periodic: [true; 3] }
+    }
+
+    /// A triclinic box with tilt factors `[xy, xz, yz]`, periodic in all dimensions.
+    pub fn triclinic(lo: [f64; 3], hi: [f64; 3], tilt: [f64; 3]) -> SimBox {
+        SimBox { lo, hi, tilt: Some(tilt), periodic: [true; 3] }
+    }
+
+    /// Build a box from a cell matrix whose rows are the cell vectors, and the position of its corner.
+    ///
+    /// A general cell must be rotated to fit LAMMPS' restricted form.  Along with the box, this
+    /// returns the rotation matrix `R`, which maps a vector `v` to `R v`.  Apply it to
+    /// atom positions relative to `origin` (which is also `lo` of the new box).
+    ///
+    /// LAMMPS also limits the tilt factors to half of the length they tilt along (e.g.
+    /// `|xy| <= lx / 2`).  Larger tilts are reduced by adding or subtracting cell vectors,
+    /// which describes the same periodic lattice with a different cell, so atoms may then
+    /// need to be wrapped back into the box.
+    ///
+    /// The box is orthogonal if the tilt factors are negligible compared to the lengths.
+    ///
+    /// Fails if the cell vectors are not right-handed.
+    pub fn from_matrix(matrix: [[f64; 3]; 3], origin: [f64; 3]) -> Result<(SimBox, [[f64; 3]; 3]), LammpsError> {
+        let [a, b, c] = matrix;
+        let a_cross_b = cross(a, b);
+        if dot(a_cross_b, c) <= 0.0 {
+            return Err(LammpsError::invalid("cell vectors must be right-handed and linearly independent"));
+        }
+        let x = normalize(a);
+        let z = normalize(a_cross_b);
+        let y = cross(z, x);
+        let rotation = [x, y, z];
+
+        let lengths = [dot(a, x), dot(b, y), dot(c, z)];
+        let tilt = reduce_tilt(lengths, [dot(b, x), dot(c, x), dot(c, y)]);
+        let hi = [origin[0] + lengths[0], origin[1] + lengths[1], origin[2] + lengths[2]];
+        let simbox = match is_negligible(lengths, tilt) {
+            true => SimBox::orthogonal(origin, hi),
+            false => SimBox::triclinic(origin, hi, tilt),
+        };
+        Ok((simbox, rotation))
+    }
+
+    /// The cell matrix, whose rows are the cell vectors.
+    pub fn matrix(&self) -> [[f64; 3]; 3] {
+        let [lx, ly, lz] = self.lengths();
+        let [xy, xz, yz] = self.tilt.unwrap_or([0.0; 3]);
+        [
+            [lx, 0.0, 0.0],
+            [xy, ly, 0.0],
+            [xz, yz, lz],
+        ]
+    }
+
+    /// `hi - lo` in each dimension.
+    pub fn lengths(&self) -> [f64; 3] {
+        [self.hi[0] - self.lo[0], self.hi[1] - self.lo[1], self.hi[2] - self.lo[2]]
+    }
+
+    /// Does the box have tilt factors?  (even if they are all zero)
+    pub fn is_triclinic(&self) -> bool { self.tilt.is_some() }
+
+    /// The volume, which does not depend on the tilt factors.
+    pub fn volume(&self) -> f64 {
+        let [lx, ly, lz] = self.lengths();
+        lx * ly * lz
+    }
+}
+
+/// # The simulation box
+impl Lammps {
+    /// Get the simulation box.
+    ///
+    /// Fails if there is no box yet.  Before LAMMPS 2020, this can only be detected
+    /// on rank 0; the other processes get a default box.
+    pub fn get_box(&self) -> Result<SimBox, LammpsError> {
+        self.check_box_exists()?;
+        let simbox = self.read_box()?;
+        // (lammps_extract_box leaves these alone on rank 0 if there is no box)
+        match simbox.lo[0].is_nan() {
+            true => Err(no_box()),
+            false => Ok(simbox),
+        }
+    }
+
+    fn read_box(&self) -> Result<SimBox, LammpsError> {
+        let mut lo = [f64::NAN; 3];
+        let mut hi = [f64::NAN; 3];
+        let (mut xy, mut yz, mut xz) = (0.0, 0.0, 0.0);
+        let mut periodicity = [0; 3];
+        let mut box_change = 0;
+        unsafe {
+            ::lammps_extract_box(
+                self.ptr, lo.as_mut_ptr(), hi.as_mut_ptr(), &mut xy, &mut yz, &mut xz,
+                periodicity.as_mut_ptr(), &mut box_change,
+            );
+        }
+        let tilt = match self.extract_global::<i32>("triclinic")? {
+            0 => None,
+            _ => Some([xy, xz, yz]),
+        };
+        let periodic = [periodicity[0] != 0, periodicity[1] != 0, periodicity[2] != 0];
+        Ok(SimBox { lo, hi, tilt, periodic })
+    }
+
+    /// Change the simulation box, using the `change_box` command.
+    ///
+    /// With `remap`, atoms are moved along with the box, keeping their fractional
+    /// coordinates.  Otherwise, they keep their positions; atoms outside the new box
+    /// may be lost (or wrapped, in periodic dimensions).
+    ///
+    /// The box may be switched between orthogonal and triclinic.  If the periodicity
+    /// changes, non-periodic dimensions get the `f` boundary style.
+    pub fn set_box(&mut self, simbox: &SimBox, remap: bool) -> Result<(), LammpsError> {
+        self.check_box_exists()?;
+        let mut numbers = simbox.lo.iter().chain(&simbox.hi).chain(simbox.tilt.iter().flatten());
+        if let Some(x) = numbers.find(|x| !x.is_finite()) {
+            return Err(LammpsError::invalid(format!("box contains a non-finite value: {}", x)));
+        }
+
+        // (without a box, change_box itself will fail on every process)
+        let old = self.read_box()?;
+        let mut args = vec![];
+        if old.periodic != simbox.periodic {
+            args.push("boundary".to_string());
+            args.extend(simbox.periodic.iter().map(|&p| if p { "p" } else { "f" }.to_string()));
+        }
+        if simbox.is_triclinic() && !old.is_triclinic() {
+            args.push("triclinic".to_string());
+        }
+
+        // LAMMPS checks the tilt factors against the lengths after each step, so the tilts
+        // must be changed first if they grow, and last if they shrink.  Otherwise, they go
+        // through zero.
+        let lengths = ["x", "y", "z"].iter().enumerate()
+            .map(|(dim, name)| format!("{} final {:?} {:?}", name, simbox.lo[dim], simbox.hi[dim]))
+            .collect::<Vec<_>>();
+        let tilts = |[xy, xz, yz]: [f64; 3]| format!("xy final {:?} xz final {:?} yz final {:?}", xy, xz, yz);
+        if simbox.is_triclinic() || old.is_triclinic() {
+            let old_tilt = old.tilt.unwrap_or([0.0; 3]);
+            let new_tilt = simbox.tilt.unwrap_or([0.0; 3]);
+            let (old_lengths, new_lengths) = (old.lengths(), simbox.lengths());
+            if tilt_fits(old_tilt, old_lengths, new_lengths) {
+                args.extend(lengths);
+                args.push(tilts(new_tilt));
+            } else if tilt_fits(new_tilt, old_lengths, new_lengths) {
+                args.push(tilts(new_tilt));
+                args.extend(lengths);
+            } else {
+                args.push(tilts([0.0; 3]));
+                args.extend(lengths);
+                args.push(tilts(new_tilt));
+            }
+        } else {
+            args.extend(lengths);
+        }
+        if old.is_triclinic() && !simbox.is_triclinic() {
+            args.push("ortho".to_string());
+        }
+        if remap {
+            args.push("remap".to_string());
+        }
+        self.command(&format!("change_box all {} units box", args.join(" ")))
+    }
+
+    /// Fail if there is certainly no box.
+    ///
+    /// (`box_exist` is not a setting before LAMMPS 2020, so older versions can't be checked)
+    pub(crate) fn check_box_exists(&self) -> Result<(), LammpsError> {
+        self.check_usable()?;
+        match self.extract_setting("box_exist") {
+            Some(0) => Err(no_box()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn no_box() -> LammpsError {
+    LammpsError::new(Severity::Error, "the simulation box has not been created")
+}
+
+// Bring the tilt factors into LAMMPS' limits by adding multiples of the cell vectors.
+// (yz first, since subtracting b from c also changes xz)
+fn reduce_tilt(lengths: [f64; 3], tilt: [f64; 3]) -> [f64; 3] {
+    let [lx, ly, _] = lengths;
+    let [mut xy, mut xz, mut yz] = tilt;
+    let n = (yz / ly).round();
+    yz -= n * ly;
+    xz -= n * xy;
+    xz -= (xz / lx).round() * lx;
+    xy -= (xy / lx).round() * lx;
+    [xy, xz, yz]
+}
+
+// Whether tilt factors are small enough to be roundoff in lengths like these.
+fn is_negligible(lengths: [f64; 3], tilt: [f64; 3]) -> bool {
+    let scale = lengths.iter().cloned().fold(0.0, f64::max);
+    tilt.iter().all(|t| t.abs() <= 1e-12 * scale)
+}
+
+// Whether LAMMPS accepts the tilt factors with any mix of two sets of lengths.
+//
+// The limit is half of the length being tilted along, but LAMMPS has also checked it
+// against the length of the tilted vector, so this takes the smaller of those.
+fn tilt_fits(tilt: [f64; 3], a: [f64; 3], b: [f64; 3]) -> bool {
+    let [lx, ly, lz] = [a[0].min(b[0]), a[1].min(b[1]), a[2].min(b[2])];
+    let [xy, xz, yz] = tilt;
+    xy.abs() <= 0.5 * lx.min(ly) && xz.abs() <= 0.5 * lx.min(lz) && yz.abs() <= 0.5 * ly.min(lz)
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 { a[0] * b[0] + a[1] * b[1] + a[2] * b[2] }
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let norm = dot(a, a).sqrt();
+    [a[0] / norm, a[1] / norm, a[2] / norm]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [[f64; 3]; 3], b: [[f64; 3]; 3]) {
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((a[i][j] - b[i][j]).abs() < 1e-12, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn apply(r: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] { [dot(r[0], v), dot(r[1], v), dot(r[2], v)] }
+
+    #[test]
+    fn round_trip() {
+        let simbox = SimBox::triclinic([1.0, 2.0, 3.0], [5.0, 7.0, 9.0], [1.5, -0.5, 2.0]);
+        let (back, rotation) = SimBox::from_matrix(simbox.matrix(), simbox.lo).unwrap();
+        assert_close([back.lo, back.hi, back.tilt.unwrap()], [simbox.lo, simbox.hi, simbox.tilt.unwrap()]);
+        assert_close(rotation, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+
+        let simbox = SimBox::orthogonal([0.0; 3], [2.0, 3.0, 4.0]);
+        assert_eq!(SimBox::from_matrix(simbox.matrix(), simbox.lo).unwrap().0, simbox);
+    }
+
+    #[test]
+    fn rotation() {
+        // the box from round_trip, rotated by 90 degrees around z and then around x
+        let matrix = [[0.0, 0.0, 4.0], [-5.0, 0.0, 1.5], [-2.0, -6.0, -0.5]];
+        let (simbox, rotation) = SimBox::from_matrix(matrix, [0.0; 3]).unwrap();
+        assert_close([simbox.hi, simbox.tilt.unwrap(), [0.0; 3]], [[4.0, 5.0, 6.0], [1.5, -0.5, 2.0], [0.0; 3]]);
+        let rotated = [apply(rotation, matrix[0]), apply(rotation, matrix[1]), apply(rotation, matrix[2])];
+        assert_close(rotated, simbox.matrix());
+    }
+
+    #[test]
+    fn tilt_reduction() {
+        let matrix = [[2.0, 0.0, 0.0], [1.6, 3.0, 0.0], [0.3, 4.0, 3.0]];
+        let (simbox, _) = SimBox::from_matrix(matrix, [0.0; 3]).unwrap();
+        // c - b = (-1.3, 1.0, 3.0), then + a = (0.7, 1.0, 3.0); b - a = (-0.4, 3.0, 0.0)
+        assert_close([simbox.tilt.unwrap(), [0.0; 3], [0.0; 3]], [[-0.4, 0.7, 1.0], [0.0; 3], [0.0; 3]]);
+        assert!((simbox.volume() - 18.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn roundoff_is_orthogonal() {
+        let angle = 0.3_f64;
+        let (s, c) = angle.sin_cos();
+        // an orthogonal box rotated around z
+        let matrix = [[2.0 * c, 2.0 * s, 0.0], [-3.0 * s, 3.0 * c, 0.0], [0.0, 0.0, 4.0]];
+        let (simbox, _) = SimBox::from_matrix(matrix, [0.0; 3]).unwrap();
+        assert!(!simbox.is_triclinic());
+        assert!(is_negligible([2.0, 3.0, 4.0], [1e-13, 0.0, 0.0]));
+        assert!(!is_negligible([2.0, 3.0, 4.0], [1e-9, 0.0, 0.0]));
+    }
+
+    #[test]
+    fn tilt_limits() {
+        let (small, large) = ([2.0, 2.0, 2.0], [10.0, 10.0, 10.0]);
+        assert!(tilt_fits([0.9, 0.0, 0.0], small, large));
+        assert!(!tilt_fits([4.0, 0.0, 0.0], small, large));
+        assert!(tilt_fits([4.0, 0.0, 0.0], large, large));
+        assert!(!tilt_fits([0.0, 0.0, 1.5], [10.0, 2.0, 10.0], large));
+    }
+
+    #[test]
+    fn left_handed() {
+        assert!(SimBox::from_matrix([[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]], [0.0; 3]).is_err());
+    }
+}
@@ -0,0 +1,368 @@
+use ::std::os::raw::c_char;
+use ::std::sync::atomic::{AtomicUsize, Ordering};
+use super::{c_string, Lammps, LammpsError};
+use super::compute::{copy_slice, non_null};
+
+/// The style of a LAMMPS variable.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VariableStyle {
+    /// A list of strings, advanced by the `next` command.
+    Index,
+    /// A range of integers, advanced by the `next` command.
+    Loop,
+    /// One string per partition.
+    World,
+    /// A list of strings, shared between partitions.
+    Universe,
+    /// Like `Loop`, but shared between partitions.
+    Uloop,
+    /// A single string.
+    String,
+    /// The value of an environment variable.
+    Getenv,
+    /// One line of a file, advanced by the `next` command.
+    File,
+    /// Per-atom values read from a file.
+    Atomfile,
+    /// An equal-style variable, formatted as a string.
+    Format,
+    /// A formula that produces a single number.
+    Equal,
+    /// A formula that produces a number for each atom.
+    Atom,
+    /// A formula that produces a global vector.
+    Vector,
+    /// The result of a Python function.
+    Python,
+    /// A number set by LAMMPS or a library caller, rather than by a formula.
+    Internal,
+    /// A style not known to `lammps-sys`.
+    Other(String),
+}
+
+impl VariableStyle {
+    fn from_name(name: &str) -> VariableStyle {
+        match name {
+            "index" => VariableStyle::Index,
+            "loop" => VariableStyle::Loop,
+            "world" => VariableStyle::World,
+            "universe" => VariableStyle::Universe,
+            "uloop" => VariableStyle::Uloop,
+            "string" => VariableStyle::String,
+            "getenv" => VariableStyle::Getenv,
+            "file" => VariableStyle::File,
+            "atomfile" => VariableStyle::Atomfile,
+            "format" => VariableStyle::Format,
+            "equal" => VariableStyle::Equal,
+            "atom" => VariableStyle::Atom,
+            "vector" => VariableStyle::Vector,
+            "python" => VariableStyle::Python,
+            "internal" => VariableStyle::Internal,
+            other => VariableStyle::Other(other.to_string()),
+        }
+    }
+}
+
+/// A variable defined in a LAMMPS instance, as listed by [`Lammps::variables`].
+///
+/// [`Lammps::variables`]: struct.Lammps.html#method.variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableInfo {
+    /// The name, as used in `v_name` and `${name}`.
+    pub name: String,
+    /// The style the variable was defined with.
+    pub style: VariableStyle,
+}
+
+/// Quote a word for use as a single argument in a LAMMPS command.
+///
+/// Words are left alone when possible.  Quoting also prevents LAMMPS from
+/// treating `$` and `#` as variable substitutions and comments.
+/// Fails for text that contains a newline, or that no kind of quotes can hold.
+pub fn quote(word: &str) -> Result<String, LammpsError> {
+    if word.contains('\n') {
+        return Err(LammpsError::invalid(format!("cannot quote text with a newline: {:?}", word)));
+    }
+    let is_special = |c: char| c.is_whitespace() || c == '#' || c == '$' || c == '"' || c == '\'';
+    match () {
+        _ if !word.is_empty() && !word.contains(is_special) && !word.ends_with('&') => Ok(word.to_string()),
+        _ if !word.contains('"') => Ok(format!("\"{}\"", word)),
+        _ if !word.contains('\'') => Ok(format!("'{}'", word)),
+        _ if !word.contains("\"\"\"") && !word.ends_with('"') => Ok(format!("\"\"\"{}\"\"\"", word)),
+        _ => Err(LammpsError::invalid(format!("cannot quote text with every kind of quote: {:?}", word))),
+    }
+}
+
+/// # Variables
+///
+/// The `define_*` methods replace any existing variable with the same name.
+/// (LAMMPS would otherwise silently ignore the redefinition of an index variable)
+/// If the new definition is rejected, the old variable is left alone.  LAMMPS only
+/// parses the formulas of equal- and atom-style variables when they are evaluated,
+/// though, so a bad formula still replaces the old variable and fails on first use.
+///
+/// Names starting with `lammps_sys_` are reserved for temporary variables of this crate.
+impl Lammps {
+    /// Define an index-style variable with one or more values.
+    pub fn define_index<V: ToString>(&mut self, name: &str, values: &[V]) -> Result<(), LammpsError> {
+        if values.is_empty() {
+            return Err(LammpsError::invalid(format!("index variable '{}' needs at least one value", name)));
+        }
+        let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        self.define_variable(name, "index", &values)
+    }
+
+    /// Define a string-style variable.
+    pub fn define_string(&mut self, name: &str, value: &str) -> Result<(), LammpsError> {
+        self.define_variable(name, "string", &[value.to_string()])
+    }
+
+    /// Define an equal-style variable from a formula, e.g. `"pe/atoms"`.
+    ///
+    /// The formula is evaluated each time the variable is used.
+    pub fn define_equal(&mut self, name: &str, formula: &str) -> Result<(), LammpsError> {
+        self.define_variable(name, "equal", &[formula.to_string()])
+    }
+
+    /// Define an atom-style variable from a formula, e.g. `"mass*vx"`.
+    pub fn define_atom(&mut self, name: &str, formula: &str) -> Result<(), LammpsError> {
+        self.define_variable(name, "atom", &[formula.to_string()])
+    }
+
+    /// Delete a variable.  Nothing happens if it does not exist.
+    pub fn delete_variable(&mut self, name: &str) -> Result<(), LammpsError> {
+        let name = check_user_variable_name(name)?;
+        self.command(&format!("variable {} delete", name))
+    }
+
+    /// Evaluate an equal-style formula, e.g. `"pe/atoms"`.
+    pub fn eval(&mut self, formula: &str) -> Result<f64, LammpsError> {
+        const TEMP_NAME: &str = "lammps_sys_eval";
+        self.define_temp(TEMP_NAME, "equal", formula)?;
+        let result = self.extract_equal(TEMP_NAME);
+        self.delete_temp(TEMP_NAME)?;
+        result
+    }
+
+    /// List the variables that are currently defined, using the `info` command.
+    ///
+    /// This goes through a temporary file.  Under MPI, only rank 0 gets a list; the
+    /// other processes get an empty one.
+    pub fn variables(&mut self) -> Result<Vec<VariableInfo>, LammpsError> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let path = ::std::env::temp_dir().join(format!(
+            "lammps-sys-info-{}-{}.txt", ::std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst),
+        ));
+        let path_str = path.to_str().ok_or_else(|| LammpsError::invalid("temp dir is not UTF-8"))?;
+
+        self.command(&format!("info variables out overwrite {}", quote(path_str)?))?;
+        let text = match ::std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            // (only rank 0 writes the file)
+            Err(ref e) if cfg!(feature = "mpi") && e.kind() == ::std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(LammpsError::invalid(format!("could not read {}: {}", path.display(), e))),
+        };
+        let _ = ::std::fs::remove_file(&path);
+        text.lines()
+            .filter(|line| line.starts_with("Variable["))
+            .map(|line| parse_info_line(line).ok_or_else(|| {
+                LammpsError::invalid(format!("could not parse the output of 'info variables': {:?}", line))
+            }))
+            .collect()
+    }
+
+    /// Evaluate an equal-style variable.
+    ///
+    /// Internal-style and python-style variables can also be evaluated this way;
+    /// LAMMPS rejects the other styles.
+    pub fn variable_equal(&mut self, name: &str) -> Result<f64, LammpsError> {
+        let name = self.check_variable_exists(name)?;
+        self.eval(&format!("v_{}", name))
+    }
+
+    /// Evaluate an atom-style (or atomfile-style) variable for the atoms owned by this process.
+    ///
+    /// Atoms that are not in `group` get a value of zero.  An equal-style variable gives
+    /// the same value for every atom in the group; LAMMPS rejects the other styles.
+    pub fn variable_atom(&mut self, name: &str, group: &str) -> Result<Vec<f64>, LammpsError> {
+        let name = self.check_variable_exists(name)?;
+        const TEMP_NAME: &str = "lammps_sys_atom";
+        self.define_temp(TEMP_NAME, "atom", &format!("v_{}", name))?;
+        let result = self.extract_atom_variable(TEMP_NAME, group);
+        self.delete_temp(TEMP_NAME)?;
+        result
+    }
+
+    // LAMMPS returns a single value or one per atom depending on the style of a variable,
+    // and reading it as the wrong one would go out of bounds.  So user variables are only
+    // read through variables of our own, whose formula `v_name` LAMMPS checks on every process.
+    fn check_variable_exists<'a>(&mut self, name: &'a str) -> Result<&'a str, LammpsError> {
+        let name = check_variable_name(name)?;
+        match self.eval(&format!("is_defined(variable,{})", name))? {
+            0.0 => Err(LammpsError::invalid(format!("variable '{}' does not exist", name))),
+            _ => Ok(name),
+        }
+    }
+
+    // Read a variable that is known to be equal-style.
+    fn extract_equal(&mut self, name: &str) -> Result<f64, LammpsError> {
+        let ptr = self.extract_variable(name, None, "equal")?;
+        // (this is a copy that we must free)
+        unsafe {
+            let value = *(ptr as *const f64);
+            ::lammps_free(ptr as *mut _);
+            Ok(value)
+        }
+    }
+
+    // Read a variable that is known to be atom-style.
+    fn extract_atom_variable(&mut self, name: &str, group: &str) -> Result<Vec<f64>, LammpsError> {
+        let len = self.nlocal()?;
+        let ptr = self.extract_variable(name, Some(group), "atom")?;
+        // (this is a copy that we must free)
+        unsafe {
+            let values = copy_slice(ptr as *const f64, len);
+            ::lammps_free(ptr as *mut _);
+            Ok(values)
+        }
+    }
+
+    fn define_variable(&mut self, name: &str, style: &str, args: &[String]) -> Result<(), LammpsError> {
+        let name = check_user_variable_name(name)?;
+        let args = args.iter().map(|arg| quote(arg)).collect::<Result<Vec<_>, _>>()?.join(" ");
+
+        // Try it under another name first, so that a bad definition doesn't cost us the old one.
+        const TEMP_NAME: &str = "lammps_sys_define";
+        self.delete_temp(TEMP_NAME)?;
+        self.command(&format!("variable {} {} {}", TEMP_NAME, style, args))?;
+        self.delete_temp(TEMP_NAME)?;
+
+        self.delete_variable(name)?;
+        self.command(&format!("variable {} {} {}", name, style, args))
+    }
+
+    // Temporary variables use reserved names, so they can't clobber the user's.
+    fn define_temp(&mut self, name: &str, style: &str, formula: &str) -> Result<(), LammpsError> {
+        self.delete_temp(name)?;
+        self.command(&format!("variable {} {} {}", name, style, quote(formula)?))
+    }
+
+    fn delete_temp(&mut self, name: &str) -> Result<(), LammpsError> {
+        self.command(&format!("variable {} delete", name))
+    }
+
+    fn extract_variable(&mut self, name: &str, group: Option<&str>, style: &str) -> Result<*mut f64, LammpsError> {
+        self.check_usable()?;
+        let c_name = c_string(name)?;
+        let c_group = match group {
+            Some(group) => Some(c_string(group)?),
+            None => None,
+        };
+        let group_ptr = c_group.as_ref().map_or(::std::ptr::null_mut(), |s| s.as_ptr() as *mut c_char);
+        let ptr = unsafe { ::lammps_extract_variable(self.ptr, c_name.as_ptr() as *mut c_char, group_ptr) };
+        self.last_error()?;
+        non_null(ptr, || format!("variable '{}' does not exist or is not {}-style", name, style))
+            .map(|ptr| ptr as *mut f64)
+    }
+}
+
+// LAMMPS only allows alphanumeric characters and underscores.
+fn check_variable_name(name: &str) -> Result<&str, LammpsError> {
+    match !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        true => Ok(name),
+        false => Err(LammpsError::invalid(format!("invalid variable name: {:?}", name))),
+    }
+}
+
+// Names with our prefix are reserved for `define_temp`.
+fn check_user_variable_name(name: &str) -> Result<&str, LammpsError> {
+    let name = check_variable_name(name)?;
+    match name.starts_with(RESERVED_PREFIX) {
+        true => Err(LammpsError::invalid(format!("variable names starting with '{}' are reserved: {:?}", RESERVED_PREFIX, name))),
+        false => Ok(name),
+    }
+}
+
+const RESERVED_PREFIX: &str = "lammps_sys_";
+
+// Parse a line like "Variable[  0]: a         , style = index     , def = 1"
+fn parse_info_line(line: &str) -> Option<VariableInfo> {
+    if !line.starts_with("Variable[") {
+        return None;
+    }
+    let rest = &line[line.find("]:")? + 2..];
+    let style_start = rest.find(", style =")?;
+    let name = rest[..style_start].trim();
+    let rest = &rest[style_start + ", style =".len()..];
+    let style = rest.split(',').next()?.trim();
+    Some(VariableInfo { name: name.to_string(), style: VariableStyle::from_name(style) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_plain() {
+        assert_eq!(quote("abc").unwrap(), "abc");
+        assert_eq!(quote("v_x*2.5").unwrap(), "v_x*2.5");
+        assert_eq!(quote("").unwrap(), "\"\"");
+    }
+
+    #[test]
+    fn quote_special() {
+        assert_eq!(quote("a b").unwrap(), "\"a b\"");
+        assert_eq!(quote("a\tb").unwrap(), "\"a\tb\"");
+        assert_eq!(quote("#comment").unwrap(), "\"#comment\"");
+        assert_eq!(quote("${x}").unwrap(), "\"${x}\"");
+        assert_eq!(quote("$x").unwrap(), "\"$x\"");
+        // (a trailing '&' would continue the command on the next line)
+        assert_eq!(quote("a&").unwrap(), "\"a&\"");
+        assert_eq!(quote("a&b").unwrap(), "a&b");
+    }
+
+    #[test]
+    fn quote_quotes() {
+        assert_eq!(quote("say \"hi\"").unwrap(), "'say \"hi\"'");
+        assert_eq!(quote("it's").unwrap(), "\"it's\"");
+        assert_eq!(quote("it's \"hi\" there").unwrap(), "\"\"\"it's \"hi\" there\"\"\"");
+        // a closing triple quote would be ambiguous
+        assert!(quote("it's \"hi\"").is_err());
+        assert!(quote("'\"\"\"'").is_err());
+        assert!(quote("a\nb").is_err());
+    }
+
+    #[test]
+    fn parse_info() {
+        assert_eq!(
+            parse_info_line("Variable[  0]: a         , style = index     , def = 1"),
+            Some(VariableInfo { name: "a".to_string(), style: VariableStyle::Index }),
+        );
+        assert_eq!(
+            parse_info_line("Variable[ 12]: temp      , style = equal     , def = 300*1.1"),
+            Some(VariableInfo { name: "temp".to_string(), style: VariableStyle::Equal }),
+        );
+        assert_eq!(
+            parse_info_line("Variable[  3]: f         , style = mystery   , def = 1"),
+            Some(VariableInfo { name: "f".to_string(), style: VariableStyle::Other("mystery".to_string()) }),
+        );
+        assert_eq!(parse_info_line("Variable information:"), None);
+        assert_eq!(parse_info_line("Info-Info-Info-Info-Info-Info-Info-Info-Info-Info-Info"), None);
+    }
+
+    #[test]
+    fn parse_info_long_name() {
+        // names longer than the column are not cut off, and push the rest of the line over
+        assert_eq!(
+            parse_info_line("Variable[  1]: a_much_longer_variable_name, style = atom      , def = mass*vx"),
+            Some(VariableInfo { name: "a_much_longer_variable_name".to_string(), style: VariableStyle::Atom }),
+        );
+    }
+
+    #[test]
+    fn reserved_names() {
+        assert!(check_user_variable_name("lammps_sys_eval").is_err());
+        assert!(check_user_variable_name("lammps_system").is_ok());
+        assert!(check_user_variable_name("a b").is_err());
+    }
+}